@@ -9,19 +9,394 @@
 //! plug-and-play. Write up the `impl`, slap it onto the [`Playlist`] struct, add whatever
 //! specialized methods you like, and profit.
 
-use std::{borrow::Cow, cell::RefCell, marker::PhantomData, ops::Deref};
-use uriparse::uri;
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    io,
+    marker::PhantomData,
+    ops::{Deref, Range},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+use uriparse::URIReference;
 
 pub mod m3u;
 pub mod plaintext;
+pub mod prelude;
+pub mod registry;
 
+/// Errors that can occur while handling playlists.
+#[derive(Debug)]
+pub enum PlaylistError {
+    /// The text could not be parsed as the expected format.
+    Parse {
+        /// The 1-based line the error was found on, if known
+        line: Option<usize>,
+        /// A description of what went wrong
+        message: String,
+    },
+    /// Reading or writing the underlying resource failed.
+    Io(io::Error),
+    /// A playlist (indirectly) references itself.
+    CircularReference {
+        /// The playlist that was found to be referenced a second time
+        path: String,
+    },
+    /// An entry can't be written without corrupting the output, like a filename or title
+    /// that contains a line break in a line-based format.
+    UnrepresentableEntry {
+        /// The number of the offending entry
+        entry: u32,
+        /// The filename that can't be written
+        filename: String,
+    },
+}
+
+impl fmt::Display for PlaylistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { line: Some(line), message } => {
+                write!(f, "parse error on line {line}: {message}")
+            }
+            Self::Parse { line: None, message } => write!(f, "parse error: {message}"),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::CircularReference { path } => {
+                write!(f, "circular playlist reference to {path}")
+            }
+            Self::UnrepresentableEntry { entry, filename } => {
+                write!(f, "entry {entry} can't be represented in this format: {filename:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlaylistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlaylistError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl PlaylistError {
+    /// Attach a line number to a parse error that doesn't have one yet.
+    pub(crate) fn at_line(self, line: usize) -> Self {
+        match self {
+            Self::Parse { line: None, message } => Self::Parse { line: Some(line), message },
+            other => other,
+        }
+    }
+}
+
+/// Check whether a filename or URI refers to a local file.
+///
+/// Anything without a scheme is considered a local path, as is anything using the `file:`
+/// scheme. Single letter schemes are Windows drive letters, not actual schemes.
 pub fn uri_is_file(uri: impl Deref<Target = str>) -> bool {
-    false
+    match URIReference::try_from(&*uri) {
+        Ok(reference) => reference.scheme().is_none_or(|scheme| {
+            scheme.as_str().len() == 1 || scheme.as_str().eq_ignore_ascii_case("file")
+        }),
+        // Backslashes and the like aren't allowed in URIs, but they are in paths. Streams
+        // with a space in them don't parse either, so fall back to looking at the scheme
+        Err(_) => {
+            !has_scheme(&uri) || uri.get(..5).is_some_and(|s| s.eq_ignore_ascii_case("file:"))
+        }
+    }
+}
+
+/// Turn a local filename or `file:` URI into a path, or [`None`] if it's remote.
+///
+/// This does not resolve relative paths against anything, it just strips the scheme.
+/// `file:` URIs are percent-decoded as well, so a `file:///C:/Music/a%20b.mp3` written by
+/// VLC becomes `C:/Music/a b.mp3`. The host has to be empty or `localhost`, and the short
+/// `file:/music/a.mp3` form without one works too. URIs naming any other host point to
+/// another machine, so those count as remote. Plain paths are taken literally.
+pub fn local_path(uri: &str) -> Option<PathBuf> {
+    if !uri_is_file(uri) {
+        return None;
+    }
+    let path = match uri.get(..5) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("file:") => {
+            let path = match uri[5..].strip_prefix("//") {
+                Some(rest) => {
+                    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                    match host.as_bytes() {
+                        [] => path,
+                        _ if host.eq_ignore_ascii_case("localhost") => path,
+                        // Some writers leave out the third slash before a drive letter
+                        [drive, b':'] if drive.is_ascii_alphabetic() => rest,
+                        _ => return None,
+                    }
+                }
+                None => &uri[5..],
+            };
+            // `file:///C:/...` carries an extra slash in front of the drive letter
+            let path = match path.as_bytes() {
+                [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+                _ => path,
+            };
+            percent_decode(path)
+        }
+        _ => Cow::Borrowed(uri),
+    };
+    Some(PathBuf::from(path.into_owned()))
+}
+
+/// Turn an absolute local path into a percent-encoded `file:` URI.
+///
+/// This is the reverse of [`local_path`], writing URIs in the same form VLC does:
+/// backslashes become slashes and Windows paths get an extra slash in front of the drive
+/// letter. Returns [`None`] for relative paths, since `file:` URIs can't express those.
+pub fn file_uri(path: impl AsRef<Path>) -> Option<String> {
+    let path = path.as_ref().to_string_lossy().replace('\\', "/");
+    let prefix = match path.as_bytes() {
+        [b'/', ..] => "file://",
+        [drive, b':', b'/', ..] if drive.is_ascii_alphabetic() => "file:///",
+        _ => return None,
+    };
+    let mut uri = String::from(prefix);
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/'
+            | b':' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    Some(uri)
+}
+
+/// Decode `%XX` escapes, leaving malformed ones as they are.
+fn percent_decode(text: &str) -> Cow<'_, str> {
+    if !text.contains('%') {
+        return Cow::Borrowed(text);
+    }
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// Normalize a local path by resolving `.` and `..` components, without touching the disk.
+///
+/// This is a purely logical operation, so symlinks are not taken into account. Excess
+/// `..` components stop at the root for absolute paths, and are kept as-is at the start of
+/// relative ones since there's nothing to resolve them against. A trailing slash is
+/// dropped, so `/music/rock/` and `/music/rock` come out the same, but the root stays `/`.
+pub fn normalize_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Check whether a filename or URI starts with a scheme, not counting Windows drive
+/// letters. Unlike [`uri_is_file`] this doesn't need the rest to be a valid URI.
+fn has_scheme(uri: &str) -> bool {
+    uri.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Get the last path component of a filename or URI, ignoring any host, query or fragment.
+///
+/// Anything with a scheme is a URI, `file:` included, so `?` and `#` end its path. Plain
+/// paths are taken literally, since both are valid in filenames.
+fn base_name(uri: &str) -> &str {
+    let path = if !has_scheme(uri) {
+        uri
+    } else {
+        let uri = uri.split(['?', '#']).next().unwrap_or(uri);
+        // Skip over the authority, hostnames are not path components
+        match uri.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |slash| &rest[slash..]),
+            None => uri,
+        }
+    };
+    path.trim_end_matches(['/', '\\']).rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Get the last path component of a filename or URI without its extension.
+fn file_stem(uri: &str) -> &str {
+    let name = base_name(uri);
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+/// Get a filename or URI in the form used by [`DedupStrategy::NormalizedPath`].
+///
+/// Backslashes count as separators in Windows paths even on other platforms, so that
+/// `C:\Music\a.mp3` and `C:/Music/a.mp3` compare equal wherever the playlist is read.
+fn comparable_path(uri: &str) -> Cow<'_, str> {
+    match local_path(uri) {
+        Some(path) => {
+            let path = path.to_string_lossy();
+            let path = if is_windows_path(&path) {
+                Cow::Owned(path.replace('\\', "/"))
+            } else {
+                path
+            };
+            Cow::Owned(normalize_path(&*path).to_string_lossy().into_owned())
+        }
+        None => Cow::Borrowed(uri),
+    }
+}
+
+/// Check whether a local path is written the Windows way, starting with a drive letter or
+/// a `\\` UNC prefix, or we're on Windows anyway.
+fn is_windows_path(path: &str) -> bool {
+    cfg!(windows) || has_windows_root(path)
+}
+
+/// Check whether a local path starts at the root of a Windows drive or share, like
+/// `C:\Music` or `\\nas\music`, regardless of the platform we're on.
+fn has_windows_root(path: &str) -> bool {
+    path.starts_with("\\\\")
+        || matches!(path.as_bytes(), [drive, b':', ..] if drive.is_ascii_alphabetic())
+}
+
+/// Check whether a path component is a root, counting a Windows drive like `C:` read on
+/// another platform as one too.
+fn is_root(component: Component) -> bool {
+    match component {
+        Component::RootDir | Component::Prefix(_) => true,
+        Component::Normal(name) => {
+            matches!(name.as_encoded_bytes(), [drive, b':'] if drive.is_ascii_alphabetic())
+        }
+        Component::CurDir | Component::ParentDir => false,
+    }
+}
+
+/// File extensions that mark an entry as a reference to another playlist.
+const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8", "pls", "xspf", "wpl", "asx"];
+
+/// Get the lowercased extension of the last path component of a filename or URI.
+fn extension(uri: &str) -> Option<String> {
+    match base_name(uri).rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => Some(ext.to_lowercase()),
+        _ => None,
+    }
+}
+
+/// Check whether a filename or URI looks like it points to another playlist.
+fn is_playlist_path(uri: &str) -> bool {
+    extension(uri).is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Format a length in seconds the short way, like `2h 58m`, `4m` or `37s`.
+fn format_length(seconds: u64) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds % 3600 / 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Pluralize `word` for `count` of them the simple English way.
+fn counted(count: usize, word: &str) -> String {
+    if count == 1 { format!("1 {word}") } else { format!("{count} {word}s") }
+}
+
+/// What to use as the title of an entry when its metadata doesn't have one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitleFallback {
+    /// The last component of the filename or URI, without its extension
+    #[default]
+    Stem,
+    /// The filename or URI exactly as it appears in the playlist
+    Filename,
+    /// An `Artist - Track` title read from the stem, so `01 Queen - Bohemian_Rhapsody.mp3`
+    /// becomes `Queen - Bohemian Rhapsody`. A leading track number is dropped and
+    /// underscores count as spaces. Stems without a ` - ` in them are used as-is.
+    ArtistTrack,
+    /// Nothing at all, leaving the title empty
+    Empty,
+}
+
+/// Read an `Artist - Track` title from a filename stem, see [`TitleFallback::ArtistTrack`].
+fn artist_track(stem: &str) -> Option<String> {
+    let stem = stem.replace('_', " ");
+    let unnumbered = stem.trim_start_matches(|c: char| c.is_ascii_digit());
+    let stem = match unnumbered.strip_prefix([' ', '.', '-']) {
+        Some(rest) if unnumbered.len() < stem.len() => {
+            rest.trim_start_matches([' ', '.', '-'])
+        }
+        _ => &stem,
+    };
+    let (artist, track) = stem.split_once(" - ")?;
+    let words = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (artist, track) = (words(artist), words(track));
+    (!artist.is_empty() && !track.is_empty()).then(|| format!("{artist} - {track}"))
+}
+
+/// Which name [`Entry::display_name_with`] should go for first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NamePreference {
+    /// The full title, as given by [`EntryMetadata::title`]
+    #[default]
+    Title,
+    /// The short name, as given by [`EntryMetadata::short_name`]
+    ShortName,
 }
 
 /// A trait to describe the barest metadata reasonably present on a playlist entry.
 ///
 /// The minimalism is inspired by extended m3u, the most common format in the wild.
+#[allow(clippy::len_without_is_empty)]
 pub trait EntryMetadata: PartialEq {
     /// If present, return the title or name set for the playlist entry.
     ///
@@ -30,8 +405,46 @@ pub trait EntryMetadata: PartialEq {
     fn title(&self) -> impl Deref<Target = str> + PartialEq;
     /// Produce the entry length, if present
     fn len(&self) -> Option<u32>;
+    /// Set the entry length, for formats that can store one.
+    ///
+    /// The default implementation discards the value, which is what formats without any
+    /// notion of an entry length should do.
+    fn set_len(&mut self, _len: Option<u32>) {}
+    /// Whether the entry is a live stream, which has no length to speak of.
+    ///
+    /// Formats that can't mark entries as live can rely on the default, which never does.
+    fn is_live(&self) -> bool {
+        false
+    }
     /// Produce all known info for this playlist entry, formatted as text.
     fn info(&self) -> impl Deref<Target = str> + PartialEq;
+    /// If present, return the name of the group this entry belongs to.
+    ///
+    /// Formats without any notion of grouping can rely on the default, which never
+    /// reports a group.
+    fn group(&self) -> Option<impl Deref<Target = str>> {
+        None::<&str>
+    }
+    /// If present, return a short name for the entry, separate from its title.
+    ///
+    /// IPTV lists use this for channel names that are shorter than what's displayed.
+    /// Formats without one can rely on the default, which never reports one.
+    fn short_name(&self) -> Option<impl Deref<Target = str>> {
+        None::<&str>
+    }
+    /// If known, return the size of the file this entry points to, in bytes.
+    ///
+    /// Formats that don't record sizes can rely on the default, which never reports one.
+    fn size(&self) -> Option<u64> {
+        None
+    }
+    /// If known, return when this entry was last played.
+    ///
+    /// This is for history-style playlists that record play times. Formats that don't
+    /// can rely on the default, which never reports one.
+    fn played_at(&self) -> Option<SystemTime> {
+        None
+    }
 }
 
 /// Basic entry information for a playlist.
@@ -41,11 +454,81 @@ pub trait Entry<M: EntryMetadata> {
     /// Get the number of the entry. Or its position in the playlist, if not specified
     fn entry_num(&self) -> u32;
     /// Get the filename or URI this entry points to
-    fn filename(&self) -> Cow<str>;
+    fn filename(&self) -> Cow<'_, str>;
+    /// Change the number of the entry
+    fn set_entry_num(&mut self, num: u32);
+    /// Change the filename or URI this entry points to
+    fn set_filename(&mut self, fname: String);
     /// If present, get the metadata object
     fn metadata(&self) -> Option<M>;
     /// Overwrite the metadata object
     fn write_metadata(&self, metadata: M);
+    /// If known, get the byte range this entry was parsed from in the original file.
+    ///
+    /// Parsers that keep track of where entries came from can report it here, allowing
+    /// editors to map an entry back onto the source text. Entries that weren't parsed from
+    /// anything, or whose format doesn't track it, return [`None`].
+    fn source_span(&self) -> Option<Range<usize>> {
+        None
+    }
+    /// Get the lowercased file extension of the filename, if it has one.
+    ///
+    /// For URIs this looks at the last component of the path, so query strings and
+    /// fragments don't get in the way. Returned as an owned [`String`] since lowercasing
+    /// may have to allocate.
+    fn extension(&self) -> Option<String> {
+        extension(&self.filename())
+    }
+    /// Get the title of this entry, using `fallback` if the metadata doesn't have one.
+    ///
+    /// An empty title counts as a missing one.
+    fn title_with_fallback(&self, fallback: TitleFallback) -> String {
+        if let Some(title) = self.metadata().map(|m| m.title().to_string())
+            && !title.is_empty()
+        {
+            return title;
+        }
+        match fallback {
+            TitleFallback::Stem => file_stem(&self.filename()).to_string(),
+            TitleFallback::Filename => self.filename().into_owned(),
+            TitleFallback::ArtistTrack => {
+                let stem = file_stem(&self.filename()).to_string();
+                artist_track(&stem).unwrap_or(stem)
+            }
+            TitleFallback::Empty => String::new(),
+        }
+    }
+    /// Get the name to show for this entry in a user interface.
+    ///
+    /// This is [`display_name_with`][Self::display_name_with] using the default
+    /// [`NamePreference`], which prefers the full title.
+    fn display_name(&self) -> String {
+        self.display_name_with(NamePreference::default())
+    }
+    /// Get the name to show for this entry in a user interface, preferring the kind of
+    /// name given by `preference`.
+    ///
+    /// When the preferred name is missing or empty the other one is used, and when both
+    /// are, the stem of the filename. For URIs the stem is percent-decoded, so
+    /// `file:///music/My%20Song.mp3` shows as `My Song`.
+    fn display_name_with(&self, preference: NamePreference) -> String {
+        let metadata = self.metadata();
+        let title = metadata.as_ref().map(|m| m.title().to_string()).filter(|t| !t.is_empty());
+        let short = metadata
+            .as_ref()
+            .and_then(|m| m.short_name().map(|n| n.to_string()))
+            .filter(|n| !n.is_empty());
+        let name = match preference {
+            NamePreference::Title => title.or(short),
+            NamePreference::ShortName => short.or(title),
+        };
+        let fname = self.filename();
+        match name {
+            Some(name) => name,
+            None if fname.contains("://") => percent_decode(file_stem(&fname)).into_owned(),
+            None => file_stem(&fname).to_string(),
+        }
+    }
 }
 
 /// A trait to describe basic metadata on the playlist itself.
@@ -56,7 +539,21 @@ pub trait PlaylistInfo {
     /// the base filename.
     fn title(&self) -> Option<impl Deref<Target = str>>;
     /// Provide the filename or URI this playlist is found. Can be relative or absolute.
-    fn filename(&self) -> Cow<str>;
+    fn filename(&self) -> Cow<'_, str>;
+    /// The fallback to use for the titles of entries without one.
+    ///
+    /// Formats that read it from [`ParseOptions::title_fallback`] report it here, the
+    /// default is the [default fallback](TitleFallback::default).
+    fn title_fallback(&self) -> TitleFallback {
+        TitleFallback::default()
+    }
+    /// The number the first entry gets when entries are numbered by their position.
+    ///
+    /// Formats that read it from [`ParseOptions::numbering_base`] report it here, so entries
+    /// added later are numbered the same way. The default is [`NumberingBase::One`].
+    fn numbering_base(&self) -> NumberingBase {
+        NumberingBase::default()
+    }
 }
 
 pub trait PlaylistFormat<P: PlaylistInfo, M: EntryMetadata, E: Entry<M>> {
@@ -65,7 +562,10 @@ pub trait PlaylistFormat<P: PlaylistInfo, M: EntryMetadata, E: Entry<M>> {
     /// Read the file from the given path into a playlist.
     fn from_path(path: impl Deref<Target = str>) -> Self;
     /// Parse a singular playlist entry.
-    fn parse_entry<S: AsRef<str>>(text: impl Into<S>) -> E;
+    ///
+    /// Malformed entry text should be reported as a [`PlaylistError::Parse`] rather than
+    /// panicking, so callers can decide whether to skip the entry or bail out entirely.
+    fn parse_entry<S: AsRef<str>>(text: impl Into<S>) -> Result<E, PlaylistError>;
     /// Parse the metadata part of a playlist entry.
     fn parse_entry_metadata<S: AsRef<str>>(text: impl Into<S>) -> M;
     /// Parse metadata about the playlist itself.
@@ -73,8 +573,9 @@ pub trait PlaylistFormat<P: PlaylistInfo, M: EntryMetadata, E: Entry<M>> {
     /// Deduplicate the entries in the playlist.
     ///
     /// This should match and deduplicate based on whatever equality is defined
-    /// for the specified [`Entry`]. Should return the amount of entries that
-    /// were removed from the playlist.
+    /// for the specified [`Entry`]. The first occurrence of every entry is kept, and
+    /// the entries that survive keep their relative order. Should return the amount
+    /// of entries that were removed from the playlist.
     fn dedup_entries(&self) -> usize;
     /// Change the path on the playlist file.
     fn rename(&self, new_name: impl Deref<Target = str>);
@@ -94,10 +595,309 @@ pub trait PlaylistFormat<P: PlaylistInfo, M: EntryMetadata, E: Entry<M>> {
     fn remove_entry(&self, entry: usize) -> E;
     /// Return a count of the amount of elements in the playlist.
     fn count(&self) -> usize;
-    ///
+    /// Merge two playlists, appending the entries of `other` to this one.
     fn merge(&self, other: Self) -> Self;
 }
 
+/// The common operations on a playlist, usable without knowing its format.
+///
+/// Unlike [`PlaylistFormat`], this trait is object safe, so any playlist can be held as a
+/// `Box<dyn PlaylistOps>`. That's what [`open`] hands out. Entries are addressed by their
+/// index and exposed through their filename, since the entry types differ per format.
+pub trait PlaylistOps {
+    /// Get the title of the playlist, if it has one.
+    fn title(&self) -> Option<String>;
+    /// Get the filename or URI the playlist is found at.
+    fn filename(&self) -> String;
+    /// Get the amount of entries in the playlist.
+    fn count(&self) -> usize;
+    /// Get the filename or URI of the entry at `index`.
+    fn entry_filename(&self, index: usize) -> Option<String>;
+    /// Get the name to show for the entry at `index`, see [`Entry::display_name`].
+    fn entry_display_name(&self, index: usize) -> Option<String>;
+    /// Add an entry without any metadata for `fname` to the end of the playlist.
+    fn add_path(&self, fname: &str);
+    /// Remove the entry at `index`, returning whether there was one.
+    fn remove_at(&self, index: usize) -> bool;
+    /// Deduplicate the entries, see [`Playlist::dedup_by`].
+    fn dedup(&self, strategy: DedupStrategy) -> usize;
+    /// Write the playlist in its own format.
+    fn write_to(
+        &self,
+        out: &mut dyn io::Write,
+        options: &SaveOptions,
+    ) -> Result<(), PlaylistError>;
+    /// Write the playlist in its own format to the file at `path`.
+    fn save_to(&self, path: &Path, options: &SaveOptions) -> Result<(), PlaylistError>;
+}
+
+/// Load the playlist at `path`, whatever format it's in.
+///
+/// The format is picked by [`FormatRegistry::builtin`](registry::FormatRegistry::builtin).
+/// Use a registry directly to load formats from elsewhere.
+///
+/// ## Errors
+/// Returns [`PlaylistError::Io`] if the file can't be read, and [`PlaylistError::Parse`]
+/// if it isn't in a known format, fails to parse, or has a line longer than
+/// [`DEFAULT_MAX_LINE_LEN`].
+pub fn open(path: &str) -> Result<Box<dyn PlaylistOps>, PlaylistError> {
+    let file = io::BufReader::new(std::fs::File::open(path)?);
+    let text = read_limited(file, DEFAULT_MAX_LINE_LEN)?;
+    let playlist = registry::FormatRegistry::builtin().detect_and_load(path, &text)?;
+    Ok(Box::new(playlist))
+}
+
+/// Read all of `reader` as UTF-8 text, giving up as soon as a line turns out to be longer
+/// than `max_line_len` bytes.
+///
+/// Lines are read one at a time and never buffered past the limit, so a file that's one
+/// endless line can't exhaust memory. Line breaks don't count towards the length.
+fn read_limited(
+    mut reader: impl io::BufRead,
+    max_line_len: usize,
+) -> Result<String, PlaylistError> {
+    let mut text = Vec::new();
+    for number in 1.. {
+        let start = text.len();
+        // Room for the longest allowed line and its `\r\n`, plus one byte to notice more
+        let limit = max_line_len.saturating_add(3) as u64;
+        let mut line = io::Read::take(&mut reader, limit);
+        if io::BufRead::read_until(&mut line, b'\n', &mut text)? == 0 {
+            break;
+        }
+        let line = &text[start..];
+        let line =
+            line.strip_suffix(b"\n").map_or(line, |l| l.strip_suffix(b"\r").unwrap_or(l));
+        if line.len() > max_line_len {
+            return Err(PlaylistError::Parse {
+                line: Some(number),
+                message: format!("line is longer than {max_line_len} bytes"),
+            });
+        }
+    }
+    String::from_utf8(text)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
+/// A user-defined canonicalization of filenames, shared between parsing and dedup.
+///
+/// Set it on [`ParseOptions::normalizer`] to have it applied to every entry that is read,
+/// and pass the same one to [`Playlist::dedup_normalized`] to match entries on it. It's
+/// cheap to clone, since clones share the function.
+#[derive(Clone)]
+pub struct Normalizer(std::sync::Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl Normalizer {
+    /// Wrap a function turning a filename or URI into its canonical form.
+    pub fn new(f: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self(std::sync::Arc::new(f))
+    }
+
+    /// Get the canonical form of `fname`.
+    pub fn apply(&self, fname: &str) -> String {
+        (self.0)(fname)
+    }
+}
+
+impl fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Normalizer(..)")
+    }
+}
+
+/// The number given to the first entry of a playlist.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberingBase {
+    /// Count from 0, the way most programming languages do
+    Zero,
+    /// Count from 1, the way most playlist formats and people do
+    #[default]
+    One,
+}
+
+impl NumberingBase {
+    /// Get the number for the entry at the 0-based `index`.
+    pub fn num(self, index: usize) -> u32 {
+        match self {
+            Self::Zero => index as u32,
+            Self::One => index as u32 + 1,
+        }
+    }
+}
+
+/// The default for [`ParseOptions::max_line_len`], 1 MiB.
+pub const DEFAULT_MAX_LINE_LEN: usize = 1024 * 1024;
+
+/// Options controlling how playlists are read.
+///
+/// Everything is off by default, reading files exactly as the format describes them. The
+/// only limit in place is [`DEFAULT_MAX_LINE_LEN`].
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Split entry lines on `,` and `;` into multiple entries, for recovering files from
+    /// exporters that put every path on a single line. Both are valid in paths, so this
+    /// will mangle any playlist that wasn't broken like that to begin with.
+    pub split_separated_lines: bool,
+    /// Treat a length of `0` as unknown rather than as zero seconds long. Which of the two
+    /// a file means depends entirely on whatever wrote it, so by default lengths are taken
+    /// at face value. Note that saving doesn't remember the `0`: an entry with a title is
+    /// written with a `-1` like any other entry of unknown length, and that reads back as a
+    /// [live stream](crate::m3u::DurationKind::Live).
+    pub zero_length_unknown: bool,
+    /// Strip double quotes wrapped around an entry, like `"C:\My Music\a.mp3"`, as some
+    /// exporters write them. Quotes inside of the path can be escaped as `\"`. Quotes are
+    /// valid in paths, so this is only safe for files known to be written like that.
+    pub unquote_paths: bool,
+    /// Rewrite every entry through a [`Normalizer`] as it is read. This runs after
+    /// everything else, so it sees the path the way it would otherwise end up in the
+    /// playlist.
+    pub normalizer: Option<Normalizer>,
+    /// The longest line, in bytes, that is accepted before giving up with a
+    /// [`PlaylistError::Parse`]. A single line of several megabytes is far more likely to
+    /// be a corrupt or malicious file than a real entry. Readers like
+    /// [`M3u::from_reader`](crate::m3u::M3u::from_reader) check this while reading, so such
+    /// a line is never buffered in full.
+    pub max_line_len: usize,
+    /// Remember the exact text of each `#EXTINF` line, so saving an entry that wasn't
+    /// edited reproduces its line byte for byte instead of reformatting it.
+    pub keep_raw_extinf: bool,
+    /// Recover entries from stray XML-style lines like `<ref href="...">`, as found in some
+    /// hybrids of M3U and ASX, by taking the URL from their `href` or `src` attribute. Other
+    /// markup lines are skipped. Paths can start with a `<` too, so this is off by default.
+    pub recover_xml_refs: bool,
+    /// The number parsed entries are counted from, for formats that don't store numbers
+    pub numbering_base: NumberingBase,
+    /// Use a comment like `# Some Title` as the title of the path right after it, for
+    /// hand-made lists that do this instead of using `#EXTINF`. Only a lone comment counts,
+    /// a block of several is left alone, as is anything with an `#EXTINF` of its own. Off by
+    /// default, since most comments really are just comments. Such titles are saved as
+    /// comments again, see [`comment_title`](crate::m3u::M3uMetadata::comment_title).
+    pub comment_titles: bool,
+    /// What the playlist should use as the title of entries that don't have one, as
+    /// reported through [`PlaylistInfo::title_fallback`]. This doesn't change what's stored
+    /// or saved, only what [`Playlist::entry_title`] reports.
+    pub title_fallback: TitleFallback,
+    /// Keep every `#EXT-X-*` directive as-is and write it back in the same place, so HLS
+    /// playlists survive having their paths rewritten even though HLS itself isn't
+    /// understood. Without this they're skipped like any other unsupported directive.
+    pub keep_hls_directives: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            split_separated_lines: false,
+            zero_length_unknown: false,
+            unquote_paths: false,
+            normalizer: None,
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            keep_raw_extinf: false,
+            recover_xml_refs: false,
+            numbering_base: NumberingBase::One,
+            comment_titles: false,
+            title_fallback: TitleFallback::default(),
+            keep_hls_directives: false,
+        }
+    }
+}
+
+/// Options controlling how playlists are written.
+///
+/// Everything is off by default, producing the plainest output the format allows.
+#[derive(Clone, Debug, Default)]
+pub struct SaveOptions {
+    /// Write an `#EXT-ABS-ENDLIST` marker as the very last line, so readers can tell a
+    /// complete file from a truncated one. Other players don't know this directive, so it
+    /// should only be enabled for files that are (also) read by Absinthium.
+    pub end_marker: bool,
+    /// Percent-encode line breaks in `file://` URIs instead of failing with
+    /// [`PlaylistError::UnrepresentableEntry`]. Plain paths and other URIs can't be encoded
+    /// without changing what they point to, so those are always rejected.
+    pub encode_newlines: bool,
+    /// Lay the file out for reading and editing by hand, for formats where that doesn't
+    /// change what it means. For EXT-M3U this puts a blank line between entries and pads
+    /// the `#EXTINF` lines so all titles line up.
+    pub pretty: bool,
+    /// When saving to a file, also write an index next to it, named like the playlist
+    /// with `.idx` appended. It lists the byte offset each entry starts at in the saved
+    /// file, one decimal number per line in entry order, so readers can seek straight to
+    /// any entry. Writing to anything other than a file never produces an index.
+    pub index_sidecar: bool,
+}
+
+/// How entries are matched against each other when looking for duplicates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DedupStrategy {
+    /// Entries are compared using their [`PartialEq`] implementation
+    #[default]
+    Exact,
+    /// Entries point to the same filename or URI, compared exactly as written
+    Filename,
+    /// Entries point to the same file once local paths are run through [`normalize_path`],
+    /// which also makes trailing slashes on directories insignificant
+    NormalizedPath,
+    /// Entries have the same title, using the filename for entries without one
+    Title,
+}
+
+/// Which cleanup steps [`Playlist::normalize`] should run. Everything is off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    /// Trim leading and trailing whitespace from filenames
+    pub trim_whitespace: bool,
+    /// Replace backslashes in local paths with forward slashes, which work everywhere
+    pub normalize_separators: bool,
+    /// Remove local entries pointing to missing files, see [`Playlist::prune_missing`]
+    pub prune_missing: bool,
+    /// Remove duplicate entries using the given strategy, see [`Playlist::dedup_by`]
+    pub dedup: Option<DedupStrategy>,
+    /// Number the entries by their position, see [`Playlist::renumber`]
+    pub renumber: bool,
+    /// The number [`renumber`](Self::renumber) starts counting from
+    pub numbering_base: NumberingBase,
+}
+
+/// How many entries each step of [`Playlist::normalize`] affected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NormalizeReport {
+    /// Entries that had whitespace trimmed from their filename
+    pub trimmed: usize,
+    /// Entries that had their path separators normalized
+    pub separators_normalized: usize,
+    /// Entries that were removed for pointing to missing files
+    pub pruned: usize,
+    /// Entries that were removed as duplicates
+    pub deduplicated: usize,
+    /// Entries that got a different number
+    pub renumbered: usize,
+}
+
+/// A description of what deduplicating a playlist would do, see [`Playlist::dedup_plan`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DedupPlan {
+    /// Every set of entries that are duplicates of each other, in order of first occurrence
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DedupPlan {
+    /// All indices that would be removed, in ascending order.
+    pub fn removed(&self) -> Vec<usize> {
+        let mut removed: Vec<usize> =
+            self.groups.iter().flat_map(|g| g.removed.iter().copied()).collect();
+        removed.sort_unstable();
+        removed
+    }
+}
+
+/// A single set of entries that are duplicates of each other.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Index of the entry that is kept, which is always the first occurrence
+    pub kept: usize,
+    /// Indices of the later occurrences that are removed, in ascending order
+    pub removed: Vec<usize>,
+}
+
 pub struct Playlist<P: PlaylistInfo, M: EntryMetadata, E: Entry<M>> {
     /// Playlist entries, kept in an [`RefCell`] for interior mutability purposes
     entries: RefCell<Vec<E>>,
@@ -115,6 +915,17 @@ impl<P: PlaylistInfo + Clone, M: EntryMetadata + Clone, E: Entry<M> + Clone>
         Self { entries: RefCell::new(entries), info: RefCell::new(info), phantom: PhantomData }
     }
 
+    /// Creates an empty playlist with room for at least `capacity` entries
+    pub fn with_capacity(info: P, capacity: usize) -> Self {
+        Self::from_parts(info, Vec::with_capacity(capacity))
+    }
+
+    /// Takes the playlist apart into its metadata and entries, the inverse of
+    /// [`from_parts`][Self::from_parts]
+    pub fn into_parts(self) -> (P, Vec<E>) {
+        (self.info.into_inner(), self.entries.into_inner())
+    }
+
     pub fn get_metadata(&self) -> P {
         self.info.borrow().clone()
     }
@@ -127,22 +938,2057 @@ impl<P: PlaylistInfo + Clone, M: EntryMetadata + Clone, E: Entry<M> + Clone>
         self.entries.borrow_mut().remove(entry)
     }
 
+    /// Add an entry to the end of the playlist, unless it duplicates an existing entry.
+    ///
+    /// Entries are matched according to `strategy`. Returns whether the entry was added.
+    pub fn append_unique(&self, entry: E, strategy: DedupStrategy) -> bool
+    where
+        E: PartialEq,
+    {
+        let mut entries = self.entries.borrow_mut();
+        if entries.iter().any(|e| Self::is_duplicate(strategy, e, &entry)) {
+            return false;
+        }
+        entries.push(entry);
+        true
+    }
+
+    /// Check whether two entries count as duplicates under `strategy`.
+    fn is_duplicate(strategy: DedupStrategy, a: &E, b: &E) -> bool
+    where
+        E: PartialEq,
+    {
+        match strategy {
+            DedupStrategy::Exact => a == b,
+            _ => Self::dedup_key(strategy, a) == Self::dedup_key(strategy, b),
+        }
+    }
+
+    /// Get the group an entry belongs to, or [`None`] if it's ungrouped.
+    fn group_of(entry: &E) -> Option<String> {
+        entry.metadata().and_then(|m| m.group().map(|g| g.to_string()))
+    }
+
+    /// Get the key entries are matched on under `strategy`, or [`None`] for
+    /// [`DedupStrategy::Exact`] which matches on the entries themselves.
+    fn dedup_key(strategy: DedupStrategy, entry: &E) -> Option<String> {
+        match strategy {
+            DedupStrategy::Exact => None,
+            DedupStrategy::Filename => Some(entry.filename().into_owned()),
+            DedupStrategy::NormalizedPath => {
+                Some(comparable_path(&entry.filename()).into_owned())
+            }
+            DedupStrategy::Title => Some(entry.title_with_fallback(TitleFallback::Filename)),
+        }
+    }
+
+    /// Remove the entry at `index` in constant time by moving the last entry into its spot.
+    ///
+    /// This works like [`Vec::swap_remove`], so it does not preserve the order of the
+    /// playlist. Returns [`None`] instead of panicking if `index` is out of bounds.
+    pub fn swap_remove(&self, index: usize) -> Option<E> {
+        let mut entries = self.entries.borrow_mut();
+        (index < entries.len()).then(|| entries.swap_remove(index))
+    }
+
     pub fn count(&self) -> usize {
         self.entries.borrow().len()
     }
 
-    pub fn merge(&self, other: Self) -> Self {
-        let new_list = self
+    /// Deduplicate entries on their filename, scoped to the group they belong to.
+    ///
+    /// The same filename showing up in two different groups is left alone, a repeat
+    /// within a single group is removed. Ungrouped entries are treated as a group of
+    /// their own. The first occurrence is kept. Returns the amount of entries that were
+    /// removed from the playlist.
+    pub fn dedup_within_groups(&self) -> usize {
+        self.dedup_by_key(|e| (Self::group_of(e), e.filename().into_owned()))
+    }
+
+    /// Deduplicate entries on the canonical form of their filename, as given by
+    /// `normalizer`.
+    ///
+    /// This is meant for use with the same [`Normalizer`] as was given to
+    /// [`ParseOptions::normalizer`], so an application can define what makes two paths the
+    /// same in one place. The first occurrence is kept, with its filename as-is. Returns
+    /// the amount of entries that were removed from the playlist.
+    pub fn dedup_normalized(&self, normalizer: &Normalizer) -> usize {
+        self.dedup_by_key(|e| normalizer.apply(&e.filename()))
+    }
+
+    /// Remove every entry that also appears in `other`, matching them under `strategy`.
+    ///
+    /// `other` acts as a blocklist and is left untouched. Duplicates within this playlist
+    /// itself are kept, use [`dedup_by`][Self::dedup_by] for those. Returns the amount of
+    /// entries that were removed from the playlist.
+    pub fn dedup_against(&self, other: &Self, strategy: DedupStrategy) -> usize
+    where
+        E: PartialEq,
+    {
+        // Copy out of `other` first, in case it's this very playlist
+        let blocked = other.entries.borrow().clone();
+        let keys: HashSet<String> =
+            blocked.iter().filter_map(|e| Self::dedup_key(strategy, e)).collect();
+        self.retain_mut(|e| match Self::dedup_key(strategy, e) {
+            Some(key) => !keys.contains(&key),
+            None => !blocked.contains(e),
+        })
+    }
+
+    /// Check whether both playlists hold the same entries under `strategy`, in any order.
+    ///
+    /// Entries are compared as a multiset, so an entry appearing twice here has to appear
+    /// twice in `other` as well. Unlike [`PartialEq`], the playlist info and the order of
+    /// the entries are not taken into account.
+    pub fn equal_ignoring_order(&self, other: &Self, strategy: DedupStrategy) -> bool
+    where
+        E: PartialEq,
+    {
+        let (ours, theirs) = (self.entries.borrow(), other.entries.borrow());
+        if ours.len() != theirs.len() {
+            return false;
+        }
+        if strategy == DedupStrategy::Exact {
+            let mut matched = vec![false; theirs.len()];
+            return ours.iter().all(|entry| {
+                let found = (0..theirs.len()).find(|&i| !matched[i] && theirs[i] == *entry);
+                found.map(|i| matched[i] = true).is_some()
+            });
+        }
+        let sorted_keys = |entries: &[E]| {
+            let mut keys: Vec<_> =
+                entries.iter().map(|e| Self::dedup_key(strategy, e)).collect();
+            keys.sort_unstable();
+            keys
+        };
+        sorted_keys(&ours) == sorted_keys(&theirs)
+    }
+
+    /// Deduplicate the entries in the playlist, matching them according to `strategy`.
+    ///
+    /// Like [`dedup_entries`][Self::dedup_entries], the first occurrence is kept and the
+    /// survivors keep their relative order. Returns the amount of entries that were removed
+    /// from the playlist.
+    pub fn dedup_by(&self, strategy: DedupStrategy) -> usize
+    where
+        E: PartialEq,
+    {
+        match strategy {
+            DedupStrategy::Exact => self.dedup_entries(),
+            _ => self.dedup_by_key(|e| Self::dedup_key(strategy, e)),
+        }
+    }
+
+    /// Deduplicate entries on a key derived from each entry.
+    ///
+    /// Entries producing the same key are considered duplicates. The first occurrence is
+    /// kept, and the relative order of the remaining entries is preserved. Returns the
+    /// amount of entries that were removed from the playlist.
+    pub fn dedup_by_key<K: Eq + Hash>(&self, key: impl Fn(&E) -> K) -> usize {
+        let mut seen = HashSet::new();
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        entries.retain(|e| seen.insert(key(e)));
+        before - entries.len()
+    }
+
+    /// Fill in missing entry lengths using a caller-provided `probe`.
+    ///
+    /// Absinthium doesn't decode any media, so apps that do (or that have a media library
+    /// lying around) can hand it a way to look up durations. The probe is only called for
+    /// entries without a known length, and receives the entry's filename. Live streams are
+    /// skipped, since they don't have a length to find. Entries without any metadata get a
+    /// default one to store the length in, provided the format can actually hold on to it.
+    pub fn infer_lengths(&self, probe: impl Fn(&str) -> Option<u32>)
+    where
+        M: Default,
+    {
+        for entry in self.entries.borrow().iter() {
+            let mut meta = entry.metadata().unwrap_or_default();
+            if meta.len().is_some() || meta.is_live() {
+                continue;
+            }
+            meta.set_len(probe(&entry.filename()));
+            if meta.len().is_some() {
+                entry.write_metadata(meta);
+            }
+        }
+    }
+
+    /// Deduplicate the entries in the playlist, based on their [`PartialEq`] implementation.
+    ///
+    /// The first occurrence of every entry is kept, and the entries that survive keep their
+    /// relative order. This is exactly what applying a [`dedup_plan`][Self::dedup_plan]
+    /// does. Returns the amount of entries that were removed from the playlist.
+    pub fn dedup_entries(&self) -> usize
+    where
+        E: PartialEq,
+    {
+        self.apply(&self.dedup_plan())
+    }
+
+    /// Work out which entries a deduplication would remove, without touching the playlist.
+    ///
+    /// Entries are matched on their [`PartialEq`] implementation. For every set of
+    /// duplicates the plan records which index is kept and which are removed, so the
+    /// removal can be undone precisely. Use [`apply`][Self::apply] to carry it out.
+    pub fn dedup_plan(&self) -> DedupPlan
+    where
+        E: PartialEq,
+    {
+        self.plan_by(DedupStrategy::Exact)
+    }
+
+    /// Find all sets of entries that match each other under `strategy`, without removing
+    /// anything.
+    ///
+    /// Each set holds the indices of its entries in ascending order, so the first one is
+    /// the entry [`dedup_by`][Self::dedup_by] would keep. Sets are ordered by that first
+    /// index, and entries without any duplicates don't show up at all.
+    pub fn find_duplicates(&self, strategy: DedupStrategy) -> Vec<Vec<usize>>
+    where
+        E: PartialEq,
+    {
+        self.plan_by(strategy)
+            .groups
+            .into_iter()
+            .map(|group| std::iter::once(group.kept).chain(group.removed).collect())
+            .collect()
+    }
+
+    /// Count how many entries [`dedup_by`][Self::dedup_by] would remove under `strategy`,
+    /// without removing anything.
+    pub fn count_duplicates(&self, strategy: DedupStrategy) -> usize
+    where
+        E: PartialEq,
+    {
+        self.plan_by(strategy).removed().len()
+    }
+
+    /// Work out which entries deduplicating under `strategy` would remove.
+    ///
+    /// Key-based strategies are grouped through a map in a single pass, only
+    /// [`DedupStrategy::Exact`] has to compare every pair of entries.
+    fn plan_by(&self, strategy: DedupStrategy) -> DedupPlan
+    where
+        E: PartialEq,
+    {
+        let entries = self.entries.borrow();
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        if strategy == DedupStrategy::Exact {
+            let mut claimed = vec![false; entries.len()];
+            for kept in 0..entries.len() {
+                if claimed[kept] {
+                    continue;
+                }
+                let removed: Vec<usize> = (kept + 1..entries.len())
+                    .filter(|&i| !claimed[i] && entries[kept] == entries[i])
+                    .collect();
+                removed.iter().for_each(|&i| claimed[i] = true);
+                if !removed.is_empty() {
+                    groups.push(DuplicateGroup { kept, removed });
+                }
+            }
+        } else {
+            let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+            for (index, entry) in entries.iter().enumerate() {
+                let key = Self::dedup_key(strategy, entry).unwrap_or_default();
+                by_key.entry(key).or_default().push(index);
+            }
+            groups.extend(
+                by_key
+                    .into_values()
+                    .filter(|set| set.len() > 1)
+                    .map(|set| DuplicateGroup { kept: set[0], removed: set[1..].to_vec() }),
+            );
+            groups.sort_unstable_by_key(|group| group.kept);
+        }
+        DedupPlan { groups }
+    }
+
+    /// Carry out a [`DedupPlan`], removing all entries it marks as removed.
+    ///
+    /// The plan should come from [`dedup_plan`][Self::dedup_plan] on this playlist, with no
+    /// changes made in between; indices are not checked against the current contents.
+    /// Returns the amount of entries that were removed from the playlist.
+    pub fn apply(&self, plan: &DedupPlan) -> usize {
+        let removed: HashSet<usize> = plan.removed().into_iter().collect();
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        let mut index = 0;
+        entries.retain(|_| {
+            index += 1;
+            !removed.contains(&(index - 1))
+        });
+        before - entries.len()
+    }
+
+    /// Deduplicate like [`dedup_entries`][Self::dedup_entries], handing back the removed
+    /// entries along with their original indices.
+    ///
+    /// The result is ordered by index and can be passed to [`reinsert`][Self::reinsert]
+    /// to undo the deduplication.
+    pub fn dedup_entries_undoable(&self) -> Vec<(usize, E)>
+    where
+        E: PartialEq,
+    {
+        let removed = self.dedup_plan().removed();
+        let mut index = 0;
+        let entries: Vec<E> = self
             .entries
+            .borrow_mut()
+            .extract_if(.., |_| {
+                index += 1;
+                removed.binary_search(&(index - 1)).is_ok()
+            })
+            .collect();
+        removed.into_iter().zip(entries).collect()
+    }
+
+    /// Put entries back at the indices they were removed from, undoing
+    /// [`dedup_entries_undoable`][Self::dedup_entries_undoable].
+    ///
+    /// Indices refer to positions in the playlist as it was before the removal, so this
+    /// only restores the original order if nothing was added or removed in between.
+    /// Indices past the end of the playlist append the entry instead.
+    pub fn reinsert(&self, mut removed: Vec<(usize, E)>) {
+        removed.sort_by_key(|(index, _)| *index);
+        let mut entries = self.entries.borrow_mut();
+        for (index, entry) in removed {
+            let index = index.min(entries.len());
+            entries.insert(index, entry);
+        }
+    }
+
+    /// Replace entries pointing to a directory with the files inside of it.
+    ///
+    /// An entry counts as a directory if it ends in a slash or points to a directory on
+    /// disk. Relative entries are looked up against `dir`. Only files with one of the given
+    /// `extensions` are included, compared like [`entries_with_extension`] does, and an
+    /// empty list includes every file. The files replace the directory entry sorted by
+    /// name, without any metadata, and are written the same way the directory was: as
+    /// `file:` URIs for a `file:` URI, as joined paths otherwise. Once anything was
+    /// expanded, all entries are numbered by their new position, counting from the
+    /// playlist's [`numbering_base`](PlaylistInfo::numbering_base). Subdirectories are not
+    /// descended into and remote entries are left alone.
+    /// Returns the amount of directory entries that were expanded.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Io`] if a directory can't be read.
+    ///
+    /// [`entries_with_extension`]: Self::entries_with_extension
+    pub fn expand_directories(
+        &self,
+        dir: &Path,
+        extensions: &[&str],
+    ) -> Result<usize, PlaylistError>
+    where
+        E: Default,
+    {
+        let extensions: Vec<String> = extensions
+            .iter()
+            .map(|ext| ext.strip_prefix('.').unwrap_or(ext).to_lowercase())
+            .collect();
+        let numbering = self.info.borrow().numbering_base();
+        let mut expanded_dirs = 0;
+        let mut expanded = Vec::new();
+        for entry in self.entries.borrow().iter() {
+            let fname = entry.filename();
+            let Some(path) = local_path(&fname).map(|path| dir.join(path)) else {
+                expanded.push(entry.clone());
+                continue;
+            };
+            if !(fname.ends_with(['/', '\\']) || path.is_dir()) || !path.exists() {
+                expanded.push(entry.clone());
+                continue;
+            }
+            let mut files = Vec::new();
+            for file in std::fs::read_dir(&path)? {
+                let file = file?;
+                let name = file.file_name().to_string_lossy().into_owned();
+                if file.file_type()?.is_file()
+                    && (extensions.is_empty()
+                        || extension(&name).is_some_and(|ext| extensions.contains(&ext)))
+                {
+                    files.push(name);
+                }
+            }
+            files.sort();
+            let base = fname.trim_end_matches(['/', '\\']);
+            let is_uri = fname.get(..5).is_some_and(|s| s.eq_ignore_ascii_case("file:"));
+            for name in files {
+                let mut file = E::default();
+                file.set_filename(
+                    is_uri
+                        .then(|| file_uri(path.join(&name)))
+                        .flatten()
+                        .unwrap_or_else(|| format!("{base}/{name}")),
+                );
+                expanded.push(file);
+            }
+            expanded_dirs += 1;
+        }
+        *self.entries.borrow_mut() = expanded;
+        if expanded_dirs > 0 {
+            self.renumber(numbering);
+        }
+        Ok(expanded_dirs)
+    }
+
+    /// Get clones of all entries with the given file extension.
+    ///
+    /// The comparison is case-insensitive and the leading dot is optional, so `"flac"`,
+    /// `".flac"` and `"FLAC"` all do the same thing. See [`Entry::extension`].
+    pub fn entries_with_extension(&self, ext: &str) -> Vec<E> {
+        let ext = ext.strip_prefix('.').unwrap_or(ext).to_lowercase();
+        self.entries
             .borrow()
             .iter()
-            .chain(other.entries.borrow().iter())
+            .filter(|e| e.extension().is_some_and(|own| own == ext))
             .cloned()
-            .collect();
-        Self {
-            entries: RefCell::new(new_list),
-            info: RefCell::clone(&self.info),
-            phantom: self.phantom,
+            .collect()
+    }
+
+    /// Count the entries per key derived from each entry.
+    ///
+    /// Useful for quick statistics, like the amount of entries per group, URI scheme or
+    /// file extension. Keys that no entry produces are simply not present in the map.
+    pub fn count_by<K: Eq + Hash>(&self, key: impl Fn(&E) -> K) -> HashMap<K, usize> {
+        let mut counts = HashMap::new();
+        for entry in self.entries.borrow().iter() {
+            *counts.entry(key(entry)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Find the numbers missing from the entry numbering, in ascending order.
+    ///
+    /// The sequence runs from the lowest entry number present to the highest, so numbers
+    /// before the first entry aren't reported. Order and repeats don't matter, only
+    /// whether every number in between is used by some entry.
+    pub fn entry_num_gaps(&self) -> Vec<u32> {
+        let nums: HashSet<u32> = self.entries.borrow().iter().map(|e| e.entry_num()).collect();
+        match (nums.iter().min(), nums.iter().max()) {
+            (Some(&min), Some(&max)) => (min..max).filter(|num| !nums.contains(num)).collect(),
+            _ => Vec::new(),
         }
     }
+
+    /// Get the distinct names of all groups in the playlist, in the order they first appear.
+    ///
+    /// Ungrouped entries don't contribute anything, so a playlist without groups gives an
+    /// empty list.
+    pub fn group_titles(&self) -> Vec<String> {
+        let mut titles: Vec<String> = Vec::new();
+        for group in self.entries.borrow().iter().filter_map(Self::group_of) {
+            if !titles.contains(&group) {
+                titles.push(group);
+            }
+        }
+        titles
+    }
+
+    /// Keep only the entries for which `f` returns `true`, allowing it to edit them as well.
+    ///
+    /// This works like [`Vec::retain_mut`], so entries are visited in order and can be
+    /// changed regardless of whether they're kept. Returns the amount of entries that were
+    /// removed from the playlist.
+    pub fn retain_mut(&self, f: impl FnMut(&mut E) -> bool) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        entries.retain_mut(f);
+        before - entries.len()
+    }
+
+    /// Remove all entries for which `pred` returns `true`, handing them back in order.
+    ///
+    /// This is the inverse of [`retain_mut`][Self::retain_mut], for when the removed
+    /// entries are still needed afterwards.
+    pub fn remove_matching(&self, pred: impl Fn(&E) -> bool) -> Vec<E> {
+        self.entries.borrow_mut().extract_if(.., |e| pred(e)).collect()
+    }
+
+    /// Call `f` on every entry in order, allowing it to edit them in place.
+    ///
+    /// The entries are borrowed once for the whole loop, so this is cheaper than going
+    /// through them one at a time and avoids cloning anything.
+    ///
+    /// ## Panics
+    /// The entries stay mutably borrowed while `f` runs, so calling any other method on
+    /// this playlist from inside of `f` will panic.
+    pub fn for_each_mut(&self, f: impl FnMut(&mut E)) {
+        self.entries.borrow_mut().iter_mut().for_each(f);
+    }
+
+    /// Shuffle every entry except the first, keeping a fixed opener in place.
+    ///
+    /// The shuffle is deterministic for a given `seed`, so the same seed on the same
+    /// playlist always produces the same order. This uses a small built-in generator
+    /// (SplitMix64) rather than anything cryptographically sound, which is plenty for
+    /// shuffling tracks.
+    pub fn shuffle_rest(&self, seed: u64) {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let mut entries = self.entries.borrow_mut();
+        let Some(rest) = entries.get_mut(1..) else { return };
+        // Fisher-Yates, back to front
+        for i in (1..rest.len()).rev() {
+            let j = (next() % (i as u64 + 1)) as usize;
+            rest.swap(i, j);
+        }
+    }
+
+    /// Apply `f` to the metadata of every entry.
+    ///
+    /// Entries without metadata are handed a default one, which is only stored if `f`
+    /// actually changed it. That way bulk edits can fill in missing fields without giving
+    /// every untouched entry an empty block of metadata.
+    pub fn map_metadata(&self, f: impl Fn(&mut M))
+    where
+        M: Default,
+    {
+        for entry in self.entries.borrow().iter() {
+            match entry.metadata() {
+                Some(mut metadata) => {
+                    f(&mut metadata);
+                    entry.write_metadata(metadata);
+                }
+                None => {
+                    let mut metadata = M::default();
+                    f(&mut metadata);
+                    if metadata != M::default() {
+                        entry.write_metadata(metadata);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compute a hash for every entry, based on its filename and metadata.
+    ///
+    /// Comparing these between two loads of a playlist is a cheap way to spot changed
+    /// entries without a full diff. The hash is FNV-1a over the filename and the
+    /// [`info`][EntryMetadata::info] of the metadata, so unlike [`Hash`] it's stable across
+    /// program runs and Rust versions. It's not meant to be collision resistant.
+    pub fn entry_hashes(&self) -> Vec<u64> {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        let fnv = |hash: u64, bytes: &[u8]| {
+            bytes.iter().fold(hash, |hash, &b| (hash ^ b as u64).wrapping_mul(PRIME))
+        };
+        self.entries
+            .borrow()
+            .iter()
+            .map(|entry| {
+                let hash = fnv(OFFSET, entry.filename().as_bytes());
+                // Separate the fields with a byte that can't appear in UTF-8 text
+                match entry.metadata() {
+                    Some(metadata) => fnv(fnv(hash, &[0xff]), metadata.info().as_bytes()),
+                    None => fnv(hash, &[0xfe]),
+                }
+            })
+            .collect()
+    }
+
+    /// Sort the playlist in place with a custom comparator.
+    ///
+    /// This works like [`slice::sort_by`], so it's a stable sort and `cmp` has to define a
+    /// total order over the entries.
+    pub fn sort_by(&self, cmp: impl FnMut(&E, &E) -> Ordering) {
+        self.entries.borrow_mut().sort_by(cmp)
+    }
+
+    /// Get the indices of the entries in the order sorting them by title would put them,
+    /// without moving anything.
+    ///
+    /// Entries without a title are sorted like [`entry_title`][Self::entry_title] names
+    /// them. The sort is stable, so entries with the same title keep their relative order.
+    pub fn sorted_indices_by_title(&self) -> Vec<usize> {
+        let entries = self.entries.borrow();
+        let fallback = self.info.borrow().title_fallback();
+        let titles: Vec<String> =
+            entries.iter().map(|e| e.title_with_fallback(fallback)).collect();
+        let mut indices: Vec<usize> = (0..entries.len()).collect();
+        indices.sort_by(|&a, &b| titles[a].cmp(&titles[b]));
+        indices
+    }
+
+    /// Get the title of the entry at `index`, or [`None`] if it's out of bounds.
+    ///
+    /// Entries without a title of their own get one according to the playlist's
+    /// [`title_fallback`](PlaylistInfo::title_fallback), as set when it was read.
+    pub fn entry_title(&self, index: usize) -> Option<String> {
+        let fallback = self.info.borrow().title_fallback();
+        self.entries.borrow().get(index).map(|e| e.title_with_fallback(fallback))
+    }
+
+    /// Sort the playlist by group, then by title within each group.
+    ///
+    /// Groups are sorted alphabetically and kept together as contiguous blocks, with all
+    /// ungrouped entries following after the last group. Entries without metadata sort as
+    /// if they have an empty title. The sort is stable, so entries that compare equal keep
+    /// their relative order.
+    pub fn sort_grouped(&self) {
+        self.entries.borrow_mut().sort_by_cached_key(|e| {
+            let group = Self::group_of(e);
+            let title = e.metadata().map(|m| m.title().to_string()).unwrap_or_default();
+            (group.is_none(), group, title)
+        })
+    }
+
+    /// Move the entries of each group together, without sorting anything else.
+    ///
+    /// Groups are placed in the order they first appear in, so a group that shows up in
+    /// several separate blocks gets all of its entries moved up to the first one. The
+    /// order within each group is kept, and ungrouped entries are treated as a group of
+    /// their own.
+    pub fn consolidate_groups(&self) {
+        let mut order: Vec<Option<String>> = Vec::new();
+        let mut entries = self.entries.borrow_mut();
+        let mut ranked: Vec<(usize, E)> = entries
+            .drain(..)
+            .map(|e| {
+                let group = Self::group_of(&e);
+                let rank = order.iter().position(|g| *g == group).unwrap_or_else(|| {
+                    order.push(group);
+                    order.len() - 1
+                });
+                (rank, e)
+            })
+            .collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        entries.extend(ranked.into_iter().map(|(_, e)| e));
+    }
+
+    /// Add up the sizes of all entries in bytes, as reported by `size_of`.
+    ///
+    /// Entries `size_of` returns [`None`] for fall back to the size in their metadata,
+    /// see [`EntryMetadata::size`]. Entries with an unknown size don't count towards the
+    /// total. This leaves finding out sizes to the caller, so it works just as well for
+    /// remote entries as it does for local files.
+    pub fn total_size(&self, size_of: impl Fn(&E) -> Option<u64>) -> u64 {
+        self.entries
+            .borrow()
+            .iter()
+            .filter_map(|e| size_of(e).or_else(|| e.metadata().and_then(|m| m.size())))
+            .sum()
+    }
+
+    /// Describe the playlist in one line, for logs and notifications.
+    ///
+    /// This reads like `Roadtrip — 42 tracks, 2h 58m (38 local, 4 streams)`. Playlists
+    /// without a title are named after their file. The length only counts entries with a
+    /// known one; when that's less than nine in ten entries the rest are mentioned, as in
+    /// `12 tracks, 40m, 5 of unknown length`, and when none are known it's left out.
+    /// Entries count as local or as streams the way [`uri_is_file`] sees them.
+    pub fn summary_line(&self) -> String {
+        let entries = self.entries.borrow();
+        let info = self.info.borrow();
+        let mut line = match info.title() {
+            Some(title) if !title.trim().is_empty() => title.trim().to_string(),
+            _ => file_stem(&info.filename()).to_string(),
+        };
+        line.push_str(" — ");
+        line.push_str(&counted(entries.len(), "track"));
+        let lengths: Vec<u64> =
+            entries.iter().filter_map(|e| e.metadata()?.len()).map(u64::from).collect();
+        if !lengths.is_empty() {
+            line.push_str(", ");
+            line.push_str(&format_length(lengths.iter().sum()));
+            let unknown = entries.len() - lengths.len();
+            if unknown * 10 > entries.len() {
+                line.push_str(&format!(", {unknown} of unknown length"));
+            }
+        }
+        if !entries.is_empty() {
+            let local = entries.iter().filter(|e| uri_is_file(e.filename())).count();
+            let streams = counted(entries.len() - local, "stream");
+            line.push_str(&format!(" ({local} local, {streams})"));
+        }
+        line
+    }
+
+    /// Drop entries from the end of the playlist until it fits within `seconds`.
+    ///
+    /// Entries with an unknown length count as zero seconds towards the total, so they're
+    /// only removed when they come after the point where the playlist goes over budget.
+    /// Returns the amount of entries that were removed from the playlist.
+    pub fn truncate_to_duration(&self, seconds: u32) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let mut total: u64 = 0;
+        let keep = entries
+            .iter()
+            .take_while(|e| {
+                total += e.metadata().and_then(|m| m.len()).unwrap_or(0) as u64;
+                total <= seconds as u64
+            })
+            .count();
+        let removed = entries.len() - keep;
+        entries.truncate(keep);
+        removed
+    }
+
+    /// Create a new playlist from the entries in the group named `group`, in order.
+    ///
+    /// Groups are matched exactly, going by [`EntryMetadata::group`]. Entries are cloned
+    /// over with their metadata, group included, as is the playlist info.
+    pub fn extract_group(&self, group: &str) -> Self {
+        let entries = self
+            .entries
+            .borrow()
+            .iter()
+            .filter(|e| Self::group_of(e).is_some_and(|g| g == group))
+            .cloned()
+            .collect();
+        Self::from_parts(self.get_metadata(), entries)
+    }
+
+    /// Create a new playlist from the entries played from `start` up to `end`.
+    ///
+    /// The window includes `start` but not `end`, going by
+    /// [`EntryMetadata::played_at`]. Entries without a play time are left out. The
+    /// playlist info is cloned over as-is.
+    pub fn filter_played_between(&self, start: SystemTime, end: SystemTime) -> Self {
+        let entries = self
+            .entries
+            .borrow()
+            .iter()
+            .filter(|e| {
+                e.metadata()
+                    .and_then(|m| m.played_at())
+                    .is_some_and(|played| start <= played && played < end)
+            })
+            .cloned()
+            .collect();
+        Self::from_parts(self.get_metadata(), entries)
+    }
+
+    /// Create a new playlist from the first `n` entries of this one.
+    ///
+    /// If `n` is larger than the amount of entries, the entire playlist is copied. The
+    /// playlist info is cloned over as-is.
+    pub fn head(&self, n: usize) -> Self {
+        let entries = self.entries.borrow();
+        let n = n.min(entries.len());
+        Self::from_parts(self.get_metadata(), entries[..n].to_vec())
+    }
+
+    /// Create a new playlist from the last `n` entries of this one.
+    ///
+    /// If `n` is larger than the amount of entries, the entire playlist is copied. The
+    /// playlist info is cloned over as-is.
+    pub fn tail(&self, n: usize) -> Self {
+        let entries = self.entries.borrow();
+        let n = n.min(entries.len());
+        Self::from_parts(self.get_metadata(), entries[entries.len() - n..].to_vec())
+    }
+
+    /// Resolve the local path an entry points to.
+    ///
+    /// Relative paths, including bare filenames, are resolved against the directory the
+    /// playlist itself lives in rather than the current working directory. The result is
+    /// passed through [`normalize_path`] so `.` and `..` are resolved as well. Paths
+    /// starting with a Windows drive letter or `\\` are absolute on any platform, with
+    /// their backslashes read as separators everywhere. Returns [`None`] for remote
+    /// entries, and for relative entries on a remote playlist.
+    pub fn resolved_path(&self, entry: &E) -> Option<PathBuf> {
+        let path = local_path(&entry.filename())?;
+        if path.is_absolute() {
+            return Some(normalize_path(path));
+        }
+        if has_windows_root(&path.to_string_lossy()) {
+            return Some(normalize_path(path.to_string_lossy().replace('\\', "/")));
+        }
+        let playlist = local_path(&self.info.borrow().filename())?;
+        Some(normalize_path(playlist.parent().unwrap_or(Path::new("")).join(path)))
+    }
+
+    /// Find the deepest directory that contains every local entry.
+    ///
+    /// Paths are resolved through [`resolved_path`][Self::resolved_path] first, and remote
+    /// entries are ignored. Returns [`None`] if there are no local entries, or if all they
+    /// share is the root, like files on different Windows drives or in unrelated top-level
+    /// directories. There's no sense in packaging up an entire drive.
+    pub fn common_base_dir(&self) -> Option<PathBuf> {
+        let entries = self.entries.borrow();
+        let mut dirs = entries
+            .iter()
+            .filter_map(|e| self.resolved_path(e))
+            .map(|path| path.parent().map(Path::to_path_buf).unwrap_or_default());
+        let mut base = dirs.next()?;
+        for dir in dirs {
+            base = base
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect();
+        }
+        base.components().any(|c| !is_root(c)).then_some(base)
+    }
+
+    /// Remove all local entries that point to a file that doesn't exist.
+    ///
+    /// Paths are resolved through [`resolved_path`][Self::resolved_path], so relative
+    /// entries are checked against the playlist directory. Remote entries are left alone.
+    /// Returns the amount of entries that were removed from the playlist.
+    pub fn prune_missing(&self) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        entries.retain(|e| self.resolved_path(e).is_none_or(|p| p.exists()));
+        before - entries.len()
+    }
+
+    /// Remove missing local entries like [`prune_missing`][Self::prune_missing], as well as
+    /// remote entries that `check` reports as unreachable.
+    ///
+    /// `check` is called with the URI of every remote entry and should return `false` for
+    /// dead streams. How it finds out is entirely up to the caller, this crate doesn't do
+    /// any networking itself. Returns the amount of entries that were removed.
+    pub fn prune_unreachable(&self, check: impl Fn(&str) -> bool) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        entries.retain(|e| match self.resolved_path(e) {
+            Some(path) => path.exists(),
+            None => check(&e.filename()),
+        });
+        before - entries.len()
+    }
+
+    /// Resolve every local entry to its canonical, absolute path on disk.
+    ///
+    /// Each entry is run through [`std::fs::canonicalize`], resolving all symlinks
+    /// along with `.` and `..`. Relative entries are looked up against `base`. Entries
+    /// that were `file:` URIs stay URIs, whatever the case of the scheme. Remote
+    /// entries, files that don't exist, and paths that aren't valid UTF-8 are left
+    /// unchanged. Returns the amount of entries that got a new filename.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Io`] if an existing path can't be canonicalized, like when
+    /// it's inside a directory that can't be read. No entries are changed in that case.
+    pub fn canonicalize_paths(&self, base: &Path) -> Result<usize, PlaylistError> {
+        let mut renamed = Vec::new();
+        for (index, entry) in self.entries.borrow().iter().enumerate() {
+            let fname = entry.filename();
+            let Some(path) = local_path(&fname).map(|path| base.join(path)) else {
+                continue;
+            };
+            if !path.exists() {
+                continue;
+            }
+            let canonical = std::fs::canonicalize(&path)?;
+            let new = if fname.get(..5).is_some_and(|s| s.eq_ignore_ascii_case("file:")) {
+                file_uri(&canonical)
+            } else {
+                canonical.to_str().map(str::to_string)
+            };
+            if let Some(new) = new.filter(|new| *new != *fname) {
+                renamed.push((index, new));
+            }
+        }
+        let mut entries = self.entries.borrow_mut();
+        for (index, fname) in &renamed {
+            entries[*index].set_filename(fname.clone());
+        }
+        Ok(renamed.len())
+    }
+
+    /// Create a new playlist with all references to other playlists inlined.
+    ///
+    /// Entries are considered to reference a playlist based on their file extension. Those
+    /// are handed to `loader`, resolved through [`resolved_path`][Self::resolved_path] if
+    /// they're local, and replaced with the entries of whatever it returns. This recurses
+    /// up to `max_depth` levels deep; references beyond that are kept as regular entries.
+    /// Relative entries of an inlined playlist are rebased onto the directory it's in, so
+    /// `song.mp3` from `sub/inner.m3u` becomes `sub/song.mp3` and still points to the same
+    /// file.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::CircularReference`] if a playlist references itself, either
+    /// directly or through any of the playlists it includes. Errors from `loader` are
+    /// passed through as-is.
+    pub fn expand_nested(
+        &self,
+        max_depth: usize,
+        loader: impl Fn(&str) -> Result<Self, PlaylistError>,
+    ) -> Result<Self, PlaylistError> {
+        let own_path = self.info.borrow().filename().into_owned();
+        let own_path = match local_path(&own_path) {
+            Some(path) => normalize_path(path).to_string_lossy().into_owned(),
+            None => own_path,
+        };
+        let entries = self.expand_entries(max_depth, &loader, &mut vec![own_path])?;
+        Ok(Self::from_parts(self.get_metadata(), entries))
+    }
+
+    /// Recursive part of [`expand_nested`][Self::expand_nested], with `visited` holding
+    /// the chain of playlists currently being expanded.
+    fn expand_entries(
+        &self,
+        depth: usize,
+        loader: &impl Fn(&str) -> Result<Self, PlaylistError>,
+        visited: &mut Vec<String>,
+    ) -> Result<Vec<E>, PlaylistError> {
+        let mut expanded = Vec::new();
+        for entry in self.entries.borrow().iter() {
+            if depth == 0 || !is_playlist_path(&entry.filename()) {
+                expanded.push(entry.clone());
+                continue;
+            }
+            let target = match self.resolved_path(entry) {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None => entry.filename().into_owned(),
+            };
+            if visited.contains(&target) {
+                return Err(PlaylistError::CircularReference { path: target });
+            }
+            let nested = loader(&target)?;
+            visited.push(target);
+            let base =
+                local_path(&entry.filename()).and_then(|p| Some(p.parent()?.to_owned()));
+            for mut inner in nested.expand_entries(depth - 1, loader, visited)? {
+                if let Some(base) = &base
+                    && let Some(path) = local_path(&inner.filename())
+                    && path.is_relative()
+                {
+                    inner.set_filename(
+                        normalize_path(base.join(path)).to_string_lossy().into(),
+                    );
+                }
+                expanded.push(inner);
+            }
+            visited.pop();
+        }
+        Ok(expanded)
+    }
+
+    /// Run any combination of cleanup steps over the playlist in one go.
+    ///
+    /// The enabled steps run in the order the fields of [`NormalizeOptions`] are listed in,
+    /// so for example deduplication sees the trimmed and normalized filenames. Returns a
+    /// report of how many entries each step affected.
+    pub fn normalize(&self, opts: NormalizeOptions) -> NormalizeReport
+    where
+        E: PartialEq,
+    {
+        let mut report = NormalizeReport::default();
+        if opts.trim_whitespace {
+            report.trimmed = self.rewrite_filenames(|fname| {
+                (fname.trim() != fname).then(|| fname.trim().to_string())
+            });
+        }
+        if opts.normalize_separators {
+            report.separators_normalized = self.rewrite_filenames(|fname| {
+                (uri_is_file(fname) && fname.contains('\\')).then(|| fname.replace('\\', "/"))
+            });
+        }
+        if opts.prune_missing {
+            report.pruned = self.prune_missing();
+        }
+        if let Some(strategy) = opts.dedup {
+            report.deduplicated = self.dedup_by(strategy);
+        }
+        if opts.renumber {
+            report.renumbered = self.renumber(opts.numbering_base);
+        }
+        report
+    }
+
+    /// Number every entry by its position in the playlist, counting from `base`.
+    ///
+    /// Returns the amount of entries that got a different number.
+    pub fn renumber(&self, base: NumberingBase) -> usize {
+        let mut renumbered = 0;
+        for (index, entry) in self.entries.borrow_mut().iter_mut().enumerate() {
+            if entry.entry_num() != base.num(index) {
+                entry.set_entry_num(base.num(index));
+                renumbered += 1;
+            }
+        }
+        renumbered
+    }
+
+    /// Replace the filename of every entry for which `f` returns a new one.
+    ///
+    /// Returns the amount of entries that got a new filename.
+    fn rewrite_filenames(&self, f: impl Fn(&str) -> Option<String>) -> usize {
+        let mut changed = 0;
+        for entry in self.entries.borrow_mut().iter_mut() {
+            if let Some(fname) = f(&entry.filename()) {
+                entry.set_filename(fname);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Replace `from` with `to` in the filename of every entry, on whole path segments.
+    ///
+    /// An occurrence of `from` only counts if it starts and ends on a segment boundary,
+    /// like the start or end of the filename or a `/` or `\\`. This makes moving a mount
+    /// point safe: replacing `/mnt/musi` leaves `/mnt/musicbrainz/a.mp3` alone. Returns the
+    /// amount of entries that changed.
+    pub fn replace_all_matching(&self, from: &str, to: &str) -> usize {
+        if from.is_empty() {
+            return 0;
+        }
+        let is_sep = |c: char| c == '/' || c == '\\';
+        self.rewrite_filenames(|fname| {
+            let mut replaced = String::new();
+            let mut last = 0;
+            for (start, _) in fname.match_indices(from) {
+                let end = start + from.len();
+                let starts_segment =
+                    start == 0 || from.starts_with(is_sep) || fname[..start].ends_with(is_sep);
+                let ends_segment = end == fname.len()
+                    || from.ends_with(is_sep)
+                    || fname[end..].starts_with(is_sep);
+                if starts_segment && ends_segment {
+                    replaced.push_str(&fname[last..start]);
+                    replaced.push_str(to);
+                    last = end;
+                }
+            }
+            (last > 0).then(|| replaced + &fname[last..])
+        })
+    }
+
+    /// Merge two playlists, consuming `other`.
+    ///
+    /// See [`merge_ref`][Self::merge_ref] for a version that leaves `other` usable.
+    pub fn merge(&self, other: Self) -> Self {
+        self.merge_ref(&other)
+    }
+
+    /// Merge two playlists, cloning the entries of `other` onto the end of this one.
+    ///
+    /// The resulting playlist keeps the info of `self`, unless `self` has no title and
+    /// `other` does. Both inputs are left untouched.
+    pub fn merge_ref(&self, other: &Self) -> Self {
+        Self::concat(&[self, other]).expect("merging always has playlists to work with")
+    }
+
+    /// Concatenate any number of playlists into a new one, in order.
+    ///
+    /// The info is taken from the first playlist with a non-empty title, falling back to
+    /// the info of the first playlist if none of them have one. This keeps the result from
+    /// ending up title-less just because it started out with an empty playlist. Returns
+    /// [`None`] if there are no playlists to take the info from.
+    pub fn concat(lists: &[&Self]) -> Option<Self> {
+        let info = lists
+            .iter()
+            .find(|list| list.info.borrow().title().is_some_and(|t| !t.is_empty()))
+            .or(lists.first())?
+            .get_metadata();
+        let entries =
+            lists.iter().flat_map(|list| list.entries.borrow().clone()).collect::<Vec<E>>();
+        Some(Self::from_parts(info, entries))
+    }
+
+    /// Concatenate any number of playlists like [`concat`][Self::concat], then deduplicate
+    /// the result under `strategy`.
+    ///
+    /// The first occurrence of an entry across all of the lists is the one that's kept.
+    /// Returns [`None`] if there are no playlists to take the info from.
+    pub fn concat_dedup(lists: &[&Self], strategy: DedupStrategy) -> Option<Self>
+    where
+        E: PartialEq,
+    {
+        let combined = Self::concat(lists)?;
+        combined.dedup_by(strategy);
+        Some(combined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m3u::{DurationKind, M3u, M3uEntryBuilder};
+
+    /// Create an empty directory to work in, unique to the test.
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("absinthium-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn parse(text: &str) -> M3u<'_> {
+        M3u::parse("/lists/test.m3u", text, &ParseOptions::default()).unwrap()
+    }
+
+    fn lengths(list: &M3u) -> Vec<Option<u32>> {
+        list.entries.borrow().iter().map(|e| e.metadata().and_then(|m| m.len())).collect()
+    }
+
+    fn filenames(list: &M3u) -> Vec<String> {
+        list.entries.borrow().iter().map(|e| e.filename().into_owned()).collect()
+    }
+
+    fn loader(path: &str) -> Result<M3u<'static>, PlaylistError> {
+        let text = match path {
+            "/lists/sub/inner.m3u" => "song.mp3\n/abs/x.mp3\nhttp://radio/s\n../deep.m3u\n",
+            "/lists/deep.m3u" => "deep.mp3\n",
+            "/lists/self.m3u" => "a.mp3\n./self.m3u\n",
+            _ => return Err(PlaylistError::Parse { line: None, message: path.to_string() }),
+        };
+        M3u::parse(path, text, &ParseOptions::default())
+    }
+
+    #[test]
+    fn dedup_within_groups_only_merges_repeats_in_one_group() {
+        let list = parse(concat!(
+            "#EXTGRP:News\nhttp://tv/one\n",
+            "#EXTGRP:Sports\nhttp://tv/one\n",
+            "#EXTGRP:News\nhttp://tv/one\n",
+            "http://tv/two\nhttp://tv/two\n",
+        ));
+        assert_eq!(list.dedup_within_groups(), 2);
+        assert_eq!(filenames(&list), ["http://tv/one", "http://tv/one", "http://tv/two"]);
+        let groups: Vec<_> = list
+            .entries
+            .borrow()
+            .iter()
+            .map(|e| e.metadata().and_then(|m| m.group().map(|g| g.to_string())))
+            .collect();
+        assert_eq!(groups, [Some("News".to_string()), Some("Sports".to_string()), None]);
+    }
+
+    #[test]
+    fn infer_lengths_fills_in_what_the_probe_finds() {
+        let list = parse("#EXTINF:10,Known\na.mp3\nb.mp3\nc.mp3\n");
+        let probed = RefCell::new(Vec::new());
+        list.infer_lengths(|fname| {
+            probed.borrow_mut().push(fname.to_string());
+            (fname == "b.mp3").then_some(5)
+        });
+        assert_eq!(*probed.borrow(), ["b.mp3", "c.mp3"]);
+        assert_eq!(lengths(&list), [Some(10), Some(5), None]);
+        assert!(list.entries.borrow()[2].metadata().is_none());
+    }
+
+    #[test]
+    fn sort_grouped_keeps_groups_together() {
+        let list = parse(concat!(
+            "#EXTINF:1,b\n#EXTGRP:Rock\nrock-b.mp3\n",
+            "#EXTINF:1,z\nloose-z.mp3\n",
+            "#EXTINF:1,y\n#EXTGRP:Jazz\njazz-y.mp3\n",
+            "#EXTINF:1,a\n#EXTGRP:Rock\nrock-a.mp3\n",
+            "#EXTINF:1,x\njazz-x.mp3\n",
+            "#EXTINF:1,a\nloose-a.mp3\n",
+        ));
+        list.sort_grouped();
+        assert_eq!(
+            filenames(&list),
+            [
+                "jazz-y.mp3",
+                "rock-a.mp3",
+                "rock-b.mp3",
+                "loose-a.mp3",
+                "jazz-x.mp3",
+                "loose-z.mp3"
+            ]
+        );
+    }
+
+    #[test]
+    fn truncate_to_duration_fits_the_budget() {
+        let text = "#EXTINF:1500,\na.mp3\nb.mp3\n#EXTINF:1800,\nc.mp3\n#EXTINF:600,\nd.mp3\n";
+        let list = parse(text);
+        assert_eq!(list.truncate_to_duration(3600), 1);
+        assert_eq!(filenames(&list), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(list.truncate_to_duration(3300), 0);
+        assert_eq!(list.truncate_to_duration(1000), 3);
+        assert_eq!(list.count(), 0);
+    }
+
+    #[test]
+    fn head_and_tail_clamp_to_the_length() {
+        let list = parse("#PLAYLIST:Mix\na.mp3\nb.mp3\nc.mp3\n");
+        assert_eq!(filenames(&list.head(2)), ["a.mp3", "b.mp3"]);
+        assert_eq!(filenames(&list.tail(2)), ["b.mp3", "c.mp3"]);
+        assert_eq!(filenames(&list.head(10)), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(filenames(&list.tail(10)), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(list.tail(0).count(), 0);
+        assert_eq!(list.head(1).info.borrow().title.as_deref(), Some("Mix"));
+        assert_eq!(list.count(), 3);
+    }
+
+    #[test]
+    fn bare_filenames_resolve_against_the_playlist() {
+        let dir = temp_dir("bare");
+        std::fs::write(dir.join("song.mp3"), "").unwrap();
+        let fname = dir.join("list.m3u").to_string_lossy().into_owned();
+        let text = "song.mp3\nmissing.mp3\n./song.mp3\nhttp://radio/s\n";
+        let list = M3u::parse(fname, text, &ParseOptions::default()).unwrap();
+        let first = list.entries.borrow()[0].clone();
+        assert_eq!(list.resolved_path(&first).unwrap(), dir.join("song.mp3"));
+        assert_eq!(list.prune_missing(), 1);
+        assert_eq!(filenames(&list), ["song.mp3", "./song.mp3", "http://radio/s"]);
+    }
+
+    #[test]
+    fn unparsable_stream_urls_are_not_pruned() {
+        let dir = temp_dir("unparsable");
+        let fname = dir.join("list.m3u").to_string_lossy().into_owned();
+        let text = "http://radio/My Song.mp3\nhttps://a/b|c.mp3\nrtsp://cam/live stream\n";
+        let list = M3u::parse(fname, text, &ParseOptions::default()).unwrap();
+        assert!(!uri_is_file("http://radio/Café.mp3"));
+        assert!(uri_is_file(r"C:\My Music\a.mp3"));
+        assert!(uri_is_file("file:///music/My Song.mp3"));
+        assert_eq!(list.prune_missing(), 0);
+        assert_eq!(list.count(), 3);
+    }
+
+    #[test]
+    fn merge_ref_leaves_both_inputs_usable() {
+        let (first, second) = (parse("a.mp3\nb.mp3\n"), parse("c.mp3\n"));
+        let merged = first.merge_ref(&second);
+        assert_eq!(filenames(&merged), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(filenames(&first), ["a.mp3", "b.mp3"]);
+        assert_eq!(filenames(&second), ["c.mp3"]);
+        assert_eq!(filenames(&first.merge(second)), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(merged.count(), 3);
+    }
+
+    #[test]
+    fn dedup_by_key_keeps_the_first_of_each_stem() {
+        let list =
+            parse("/a/song.flac\n/b/song.mp3\nother.mp3\nhttp://x/song.ogg\nOther.mp3\n");
+        let stem = |e: &crate::m3u::M3uEntry| {
+            let name = e.filename();
+            let name = name.rsplit('/').next().unwrap_or_default();
+            name.split('.').next().unwrap_or_default().to_string()
+        };
+        assert_eq!(list.dedup_by_key(stem), 2);
+        assert_eq!(filenames(&list), ["/a/song.flac", "other.mp3", "Other.mp3"]);
+    }
+
+    #[test]
+    fn sort_by_takes_a_custom_comparator() {
+        let list = parse("ccc.mp3\na.mp3\nbb.mp3\nd.mp3\n");
+        list.sort_by(|a, b| a.filename().len().cmp(&b.filename().len()));
+        // Stable, so the two shortest keep their order
+        assert_eq!(filenames(&list), ["a.mp3", "d.mp3", "bb.mp3", "ccc.mp3"]);
+    }
+
+    #[test]
+    fn dedup_plan_matches_dedup_entries() {
+        let text = "a.mp3\nb.mp3\na.mp3\n#EXTINF:3,B\nb.mp3\nb.mp3\nc.mp3\na.mp3\n";
+        let list = parse(text);
+        let plan = list.dedup_plan();
+        assert_eq!(
+            plan.groups,
+            [
+                DuplicateGroup { kept: 0, removed: vec![2, 6] },
+                DuplicateGroup { kept: 1, removed: vec![4] },
+            ]
+        );
+        assert_eq!(plan.removed(), [2, 4, 6]);
+        assert_eq!(list.count(), 7);
+        assert_eq!(list.apply(&plan), 3);
+        let expected = parse(text);
+        assert_eq!(expected.dedup_entries(), 3);
+        assert_eq!(filenames(&list), filenames(&expected));
+        assert_eq!(lengths(&list), [None, None, Some(3), None]);
+    }
+
+    #[test]
+    fn normalize_path_resolves_dots_logically() {
+        let cases = [
+            ("./a.mp3", "a.mp3"),
+            ("music/./a.mp3", "music/a.mp3"),
+            ("../a.mp3", "../a.mp3"),
+            ("music/../a.mp3", "a.mp3"),
+            ("../Music/./a.mp3", "../Music/a.mp3"),
+            ("a/b/../../../c/./d.mp3", "../c/d.mp3"),
+            ("/music/../../a.mp3", "/a.mp3"),
+            ("/..", "/"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(normalize_path(path), Path::new(expected), "{path}");
+        }
+    }
+
+    #[test]
+    fn count_by_counts_entries_per_key() {
+        let list =
+            parse("a.mp3\nb.MP3\nc.flac\nhttp://radio/stream\nhttp://radio/d.mp3?x=1\n");
+        let counts = list.count_by(|e| uri_is_file(e.filename()));
+        assert_eq!(counts, HashMap::from([(true, 3), (false, 2)]));
+    }
+
+    #[test]
+    fn merging_prefers_the_first_titled_info() {
+        let (untitled, titled) = (parse("#PLAYLIST:\n"), parse("#PLAYLIST:Roadtrip\na.mp3\n"));
+        assert_eq!(
+            untitled.merge_ref(&titled).info.borrow().title.as_deref(),
+            Some("Roadtrip")
+        );
+        let other = parse("#PLAYLIST:Other\nb.mp3\n");
+        let combined = M3u::concat(&[&untitled, &titled, &other]).unwrap();
+        assert_eq!(combined.info.borrow().title.as_deref(), Some("Roadtrip"));
+        assert_eq!(filenames(&combined), ["a.mp3", "b.mp3"]);
+        // With no title anywhere, the first info is used
+        let plain = parse("c.mp3\n");
+        assert_eq!(untitled.merge_ref(&plain).info.borrow().title.as_deref(), Some(""));
+        assert!(M3u::concat(&[]).is_none());
+    }
+
+    #[test]
+    fn swap_remove_moves_the_last_entry_into_the_hole() {
+        let list = parse("a.mp3\nb.mp3\nc.mp3\nd.mp3\n");
+        assert_eq!(list.swap_remove(1).unwrap().fname, "b.mp3");
+        assert_eq!(filenames(&list), ["a.mp3", "d.mp3", "c.mp3"]);
+        assert!(list.swap_remove(3).is_none());
+        assert_eq!(list.swap_remove(2).unwrap().fname, "c.mp3");
+        assert_eq!(filenames(&list), ["a.mp3", "d.mp3"]);
+    }
+
+    #[test]
+    fn retain_mut_rewrites_kept_entries_and_drops_the_rest() {
+        let list = parse("/old/a.mp3\n/old/dead.mp3\nhttp://radio/s\n/old/b.mp3\n");
+        let removed = list.retain_mut(|e| {
+            if e.fname.contains("dead") {
+                return false;
+            }
+            if let Some(rest) = e.fname.strip_prefix("/old/") {
+                let fname = format!("/new/{rest}");
+                e.fname = fname.into();
+            }
+            true
+        });
+        assert_eq!(removed, 1);
+        assert_eq!(filenames(&list), ["/new/a.mp3", "http://radio/s", "/new/b.mp3"]);
+    }
+
+    #[test]
+    fn append_unique_rejects_duplicates() {
+        let list = parse("#EXTINF:3,A\n/music/a.mp3\nb.mp3\n");
+        let entry = |fname| M3uEntryBuilder::new(fname).build();
+        assert!(!list.append_unique(entry("/music/a.mp3"), DedupStrategy::Filename));
+        assert!(
+            !list.append_unique(entry("/music/x/../a.mp3"), DedupStrategy::NormalizedPath)
+        );
+        // Without its metadata it's a different entry altogether
+        assert!(list.append_unique(entry("/music/a.mp3"), DedupStrategy::Exact));
+        assert!(list.append_unique(entry("c.mp3"), DedupStrategy::Filename));
+        assert_eq!(filenames(&list), ["/music/a.mp3", "b.mp3", "/music/a.mp3", "c.mp3"]);
+    }
+
+    #[test]
+    fn shuffle_rest_keeps_the_opener_and_is_seeded() {
+        let text: String = (0..20).map(|i| format!("{i}.mp3\n")).collect();
+        let shuffled = |seed| {
+            let list = parse(&text);
+            list.shuffle_rest(seed);
+            filenames(&list)
+        };
+        let (first, again, other) = (shuffled(7), shuffled(7), shuffled(8));
+        assert_eq!(first[0], "0.mp3");
+        assert_eq!(other[0], "0.mp3");
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert_ne!(first, filenames(&parse(&text)));
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|fname| file_stem(fname).parse::<u32>().unwrap());
+        assert_eq!(sorted, filenames(&parse(&text)));
+        // Nothing to shuffle in lists of one or none
+        parse("a.mp3\n").shuffle_rest(7);
+        parse("").shuffle_rest(7);
+    }
+
+    #[test]
+    fn map_metadata_uppercases_every_title() {
+        let list =
+            parse("#EXTINF:3,Intro\na.mp3\n#EXTINF:4,Bohemian Rhapsody\nb.mp3\nc.mp3\n");
+        list.map_metadata(|m| m.title = m.title.to_uppercase());
+        let titles: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.metadata().map(|m| m.title)).collect();
+        assert_eq!(
+            titles,
+            [Some("INTRO".to_string()), Some("BOHEMIAN RHAPSODY".to_string()), None]
+        );
+        // Untouched defaults aren't stored
+        assert!(list.entries.borrow()[2].metadata().is_none());
+        list.map_metadata(|m| m.group = Some("Mix".to_string()));
+        let groups: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.metadata().and_then(|m| m.group)).collect();
+        assert_eq!(groups, vec![Some("Mix".to_string()); 3]);
+        assert_eq!(lengths(&list), [Some(3), Some(4), None]);
+    }
+
+    #[test]
+    fn entry_hashes_only_change_with_the_entry() {
+        let text = "#EXTINF:3,A\na.mp3\nb.mp3\n#EXTINF:3,A\nc.mp3\n";
+        let (list, reloaded) = (parse(text), parse(text));
+        let hashes = list.entry_hashes();
+        assert_eq!(hashes, reloaded.entry_hashes());
+        assert_eq!(hashes.iter().collect::<HashSet<_>>().len(), 3);
+        list.entries.borrow()[0].metadata.borrow_mut().as_mut().unwrap().title = "B".into();
+        let changed = list.entry_hashes();
+        assert_ne!(changed[0], hashes[0]);
+        assert_eq!(changed[1..], hashes[1..]);
+    }
+
+    #[test]
+    fn entries_with_extension_filters_case_insensitively() {
+        let list = parse("a.flac\nb.mp3\nc.FLAC\nhttp://radio/d.flac?x=1\ne.flac.mp3\nflac\n");
+        let flac = |ext| {
+            let entries = list.entries_with_extension(ext);
+            entries.iter().map(|e| e.fname.to_string()).collect::<Vec<_>>()
+        };
+        let expected = ["a.flac", "c.FLAC", "http://radio/d.flac?x=1"];
+        assert_eq!(flac("flac"), expected);
+        assert_eq!(flac(".FLAC"), expected);
+        assert!(flac("ogg").is_empty());
+        assert_eq!(list.count(), 6);
+    }
+
+    #[test]
+    fn normalize_reports_every_step() {
+        let dir = temp_dir("normalize");
+        std::fs::create_dir(dir.join("album")).unwrap();
+        std::fs::write(dir.join("album/a.mp3"), "").unwrap();
+        std::fs::write(dir.join("album/b.mp3"), "").unwrap();
+        let fname = dir.join("list.m3u").to_string_lossy().into_owned();
+        let text = concat!(
+            "album/a.mp3\n",
+            "album\\b.mp3\n",
+            "album/missing.mp3\n",
+            "album/./a.mp3\n",
+            "http://radio/s\n",
+            "album/b.mp3\n",
+        );
+        let list = M3u::parse(fname, text, &ParseOptions::default()).unwrap();
+        // Reading already trims lines, but edits can leave whitespace behind
+        list.entries.borrow_mut()[0].set_filename("  album/a.mp3".into());
+        list.entries.borrow_mut()[3].set_filename("album/./a.mp3\t".into());
+        list.entries.borrow_mut()[5].num = 2;
+        let report = list.normalize(NormalizeOptions {
+            trim_whitespace: true,
+            normalize_separators: true,
+            prune_missing: true,
+            dedup: Some(DedupStrategy::NormalizedPath),
+            renumber: true,
+            numbering_base: NumberingBase::Zero,
+        });
+        let expected = NormalizeReport {
+            trimmed: 2,
+            separators_normalized: 1,
+            pruned: 1,
+            deduplicated: 2,
+            renumbered: 3,
+        };
+        assert_eq!(report, expected);
+        assert_eq!(filenames(&list), ["album/a.mp3", "album/b.mp3", "http://radio/s"]);
+        let nums: Vec<_> = list.entries.borrow().iter().map(|e| e.num).collect();
+        assert_eq!(nums, [0, 1, 2]);
+        assert_eq!(list.normalize(NormalizeOptions::default()), NormalizeReport::default());
+    }
+
+    #[test]
+    fn expand_directories_replaces_directories_with_their_files() {
+        let dir = temp_dir("expand");
+        for file in ["album/2.MP3", "album/1.mp3", "album/cover.jpg", "loose/x.ogg", "a.mp3"] {
+            std::fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        std::fs::create_dir(dir.join("album/bonus")).unwrap();
+        let text = "album/\nloose\na.mp3\nmissing/\nhttp://radio/dir/\n";
+        let list = parse(text);
+        assert_eq!(list.expand_directories(&dir, &[".mp3", "ogg"]).unwrap(), 2);
+        assert_eq!(
+            filenames(&list),
+            [
+                "album/1.mp3",
+                "album/2.MP3",
+                "loose/x.ogg",
+                "a.mp3",
+                "missing/",
+                "http://radio/dir/"
+            ]
+        );
+        // No extensions at all includes every file, but still no subdirectories
+        let list = parse(text);
+        assert_eq!(list.expand_directories(&dir, &[]).unwrap(), 2);
+        assert_eq!(filenames(&list)[..3], ["album/1.mp3", "album/2.MP3", "album/cover.jpg"]);
+    }
+
+    #[test]
+    fn expand_directories_keeps_uris_and_renumbers() {
+        let dir = temp_dir("expand-uri");
+        std::fs::create_dir(dir.join("my album")).unwrap();
+        for file in ["my album/1.mp3", "my album/2.mp3"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let uri = file_uri(dir.join("my album")).unwrap() + "/";
+        let text = format!("{uri}\nb.mp3\nc.mp3\n");
+        let list = parse(&text);
+        assert_eq!(list.expand_directories(&dir, &[]).unwrap(), 1);
+        assert_eq!(
+            filenames(&list),
+            [
+                file_uri(dir.join("my album/1.mp3")).unwrap(),
+                file_uri(dir.join("my album/2.mp3")).unwrap(),
+                "b.mp3".to_string(),
+                "c.mp3".to_string(),
+            ]
+        );
+        let nums: Vec<_> = list.entries.borrow().iter().map(|e| e.num).collect();
+        assert_eq!(nums, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn for_each_mut_edits_entries_in_place() {
+        let list = parse("a.mp3\nb.mp3\nc.mp3\n");
+        list.for_each_mut(|e| e.set_entry_num(e.entry_num() + 10));
+        let nums: Vec<_> = list.entries.borrow().iter().map(|e| e.num).collect();
+        assert_eq!(nums, [11, 12, 13]);
+        assert_eq!(filenames(&list), ["a.mp3", "b.mp3", "c.mp3"]);
+    }
+
+    #[test]
+    fn group_titles_lists_distinct_groups_in_order() {
+        let list = parse(concat!(
+            "#EXTINF:-1 group-title=\"News\",A\nhttp://tv/a\n",
+            "http://tv/loose\n",
+            "#EXTGRP:Sports\nhttp://tv/b\n",
+            "#EXTINF:-1 group-title=\"News\",C\nhttp://tv/c\n",
+            "#EXTINF:-1 group-title=\"Ignored\",D\n#EXTGRP:Kids\nhttp://tv/d\n",
+            "#EXTGRP:Sports\nhttp://tv/e\n",
+        ));
+        assert_eq!(list.group_titles(), ["News", "Sports", "Kids"]);
+        assert!(parse("a.mp3\nb.mp3\n").group_titles().is_empty());
+    }
+
+    #[test]
+    fn consolidate_groups_makes_groups_contiguous() {
+        let list = parse(concat!(
+            "#EXTGRP:News\nnews-1\n",
+            "#EXTGRP:Sports\nsports-1\n",
+            "loose-1\n",
+            "#EXTGRP:News\nnews-2\n",
+            "#EXTGRP:Sports\nsports-2\n",
+            "loose-2\n",
+            "#EXTGRP:News\nnews-3\n",
+        ));
+        list.consolidate_groups();
+        assert_eq!(
+            filenames(&list),
+            ["news-1", "news-2", "news-3", "sports-1", "sports-2", "loose-1", "loose-2"]
+        );
+    }
+
+    #[test]
+    fn one_normalizer_serves_parsing_and_dedup() {
+        let normalizer = Normalizer::new(|fname| match fname.rsplit_once('.') {
+            Some((rest, ext)) if !ext.contains('/') => rest.to_string(),
+            _ => fname.to_string(),
+        });
+        let text = "/music/Song.mp3\n/music/Song.flac\n/music/Other.ogg\n/music/Song\n";
+        let options =
+            ParseOptions { normalizer: Some(normalizer.clone()), ..Default::default() };
+        let list = M3u::parse("/lists/test.m3u", text, &options).unwrap();
+        assert_eq!(
+            filenames(&list),
+            ["/music/Song", "/music/Song", "/music/Other", "/music/Song"]
+        );
+        assert_eq!(list.dedup_normalized(&normalizer), 2);
+        // The originals are kept as written, matched only through the normalizer
+        let list = parse(text);
+        assert_eq!(list.dedup_normalized(&normalizer), 2);
+        assert_eq!(filenames(&list), ["/music/Song.mp3", "/music/Other.ogg"]);
+    }
+
+    #[test]
+    fn entry_num_gaps_finds_missing_numbers() {
+        let list = parse("a.mp3\nb.mp3\nc.mp3\nd.mp3\n");
+        assert!(list.entry_num_gaps().is_empty());
+        list.entries.borrow_mut()[2].num = 4;
+        list.entries.borrow_mut()[3].num = 7;
+        assert_eq!(list.entry_num_gaps(), [3, 5, 6]);
+        list.remove_entry(3);
+        assert_eq!(list.entry_num_gaps(), [3]);
+        assert!(parse("").entry_num_gaps().is_empty());
+    }
+
+    #[test]
+    fn display_name_falls_back_to_the_stem() {
+        let list = parse(concat!(
+            "#EXTINF:3,Named Song\n/music/named.mp3\n",
+            "/music/Bare Track.flac\n",
+            "file:///music/My%20Song.mp3\n",
+        ));
+        let names: Vec<_> = list.entries.borrow().iter().map(Entry::display_name).collect();
+        assert_eq!(names, ["Named Song", "Bare Track", "My Song"]);
+    }
+
+    #[test]
+    fn replace_all_matching_moves_a_mount_point() {
+        let list = parse("/mnt/musi/a.mp3\n/mnt/musicbrainz/b.mp3\n/mnt/musi\nother/c.mp3\n");
+        assert_eq!(list.replace_all_matching("/mnt/musi", "/media/music"), 2);
+        let moved =
+            ["/media/music/a.mp3", "/mnt/musicbrainz/b.mp3", "/media/music", "other/c.mp3"];
+        assert_eq!(filenames(&list), moved);
+        assert_eq!(list.replace_all_matching("/musi", "/x"), 0);
+        assert_eq!(list.replace_all_matching("", "/x"), 0);
+    }
+
+    #[test]
+    fn into_parts_undoes_from_parts() {
+        let list = parse("#PLAYLIST:Mix\n#EXTINF:3,A\na.mp3\nb.mp3\n");
+        let entries = list.entries.borrow().clone();
+        let (info, parts) = list.into_parts();
+        assert_eq!(parts, entries);
+        assert_eq!(info.title.as_deref(), Some("Mix"));
+        let rebuilt = M3u::from_parts(info, parts);
+        assert_eq!(filenames(&rebuilt), ["a.mp3", "b.mp3"]);
+        assert_eq!(rebuilt.info.borrow().title.as_deref(), Some("Mix"));
+    }
+
+    #[test]
+    fn concat_dedup_keeps_one_of_each() {
+        let monday = parse("a.mp3\nb.mp3\nc.mp3\n");
+        let tuesday = parse("b.mp3\n./c.mp3\nd.mp3\n");
+        let wednesday = parse("d.mp3\na.mp3\ne.mp3\ne.mp3\n");
+        let lists = [&monday, &tuesday, &wednesday];
+        let master = M3u::concat_dedup(&lists, DedupStrategy::NormalizedPath).unwrap();
+        assert_eq!(filenames(&master), ["a.mp3", "b.mp3", "c.mp3", "d.mp3", "e.mp3"]);
+        let by_name = M3u::concat_dedup(&lists, DedupStrategy::Filename).unwrap();
+        assert_eq!(by_name.entries.borrow().len(), 6);
+        assert_eq!(monday.entries.borrow().len(), 3);
+        assert!(M3u::concat_dedup(&[], DedupStrategy::Exact).is_none());
+    }
+
+    #[test]
+    fn prune_unreachable_asks_about_remote_entries() {
+        let dir = std::path::absolute(temp_dir("prune-unreachable")).unwrap();
+        std::fs::write(dir.join("here.mp3"), b"").unwrap();
+        let text = format!(
+            "{0}/here.mp3\n{0}/gone.mp3\nhttp://radio/live\nhttp://radio/dead\n",
+            dir.display()
+        );
+        let fname = dir.join("list.m3u").to_string_lossy().into_owned();
+        let list = M3u::parse(fname, &text, &ParseOptions::default()).unwrap();
+        let asked = RefCell::new(Vec::new());
+        let removed = list.prune_unreachable(|uri| {
+            asked.borrow_mut().push(uri.to_string());
+            uri != "http://radio/dead"
+        });
+        assert_eq!(removed, 2);
+        assert_eq!(asked.into_inner(), ["http://radio/live", "http://radio/dead"]);
+        let here = format!("{}/here.mp3", dir.display());
+        assert_eq!(filenames(&list), [here.as_str(), "http://radio/live"]);
+    }
+
+    #[test]
+    fn count_duplicates_matches_dedup_by() {
+        let text = "#EXTINF:1,Same\na.mp3\nb.mp3\n./a.mp3\n#EXTINF:1,Same\nc.mp3\nb.mp3\n";
+        let strategies = [
+            DedupStrategy::Exact,
+            DedupStrategy::Filename,
+            DedupStrategy::NormalizedPath,
+            DedupStrategy::Title,
+        ];
+        let counts: Vec<_> = strategies
+            .into_iter()
+            .map(|strategy| {
+                let list = parse(text);
+                let counted = list.count_duplicates(strategy);
+                assert_eq!(list.entries.borrow().len(), 5);
+                assert_eq!(counted, list.dedup_by(strategy), "{strategy:?}");
+                counted
+            })
+            .collect();
+        assert_eq!(counts, [1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn extract_group_takes_one_group() {
+        let list = parse(concat!(
+            "#EXTM3U\n",
+            "#EXTINF:-1 group-title=\"News\",BBC\nhttp://tv/bbc\n",
+            "#EXTINF:-1 group-title=\"Sports\",ESPN\nhttp://tv/espn\n",
+            "#EXTGRP:News\n#EXTINF:-1,CNN\nhttp://tv/cnn\n",
+            "#EXTINF:-1,Loose\nhttp://tv/loose\n",
+        ));
+        let news = list.extract_group("News");
+        assert_eq!(filenames(&news), ["http://tv/bbc", "http://tv/cnn"]);
+        let groups: Vec<_> = news
+            .entries
+            .borrow()
+            .iter()
+            .map(|e| e.metadata().unwrap().group().map(|g| g.to_string()))
+            .collect();
+        assert_eq!(groups, [Some("News".to_string()), Some("News".to_string())]);
+        assert_eq!(filenames(&list.extract_group("Sports")), ["http://tv/espn"]);
+        assert!(list.extract_group("news").entries.borrow().is_empty());
+        assert_eq!(list.entries.borrow().len(), 4);
+    }
+
+    #[test]
+    fn dedup_against_drops_what_we_already_have() {
+        let new = parse("a.mp3\nb.mp3\nc.mp3\nc.mp3\nd.mp3\n");
+        let have = parse("./b.mp3\nd.mp3\nx.mp3\n");
+        assert_eq!(new.dedup_against(&have, DedupStrategy::NormalizedPath), 2);
+        assert_eq!(filenames(&new), ["a.mp3", "c.mp3", "c.mp3"]);
+        assert_eq!(filenames(&have), ["./b.mp3", "d.mp3", "x.mp3"]);
+        assert_eq!(have.dedup_against(&have, DedupStrategy::Filename), 3);
+    }
+
+    #[test]
+    fn mixed_windows_separators_dedup_as_one_file() {
+        let list = parse("C:\\Music\\a.mp3\nC:/Music/a.mp3\nC:/Music/b.mp3\n");
+        assert_eq!(list.find_duplicates(DedupStrategy::NormalizedPath), [vec![0, 1]]);
+        assert!(list.find_duplicates(DedupStrategy::Filename).is_empty());
+        assert_eq!(list.dedup_by(DedupStrategy::NormalizedPath), 1);
+        assert_eq!(filenames(&list), ["C:\\Music\\a.mp3", "C:/Music/b.mp3"]);
+    }
+
+    #[test]
+    fn with_capacity_reserves_room() {
+        let list: M3u = M3u::with_capacity(crate::m3u::M3uInfo::default(), 64);
+        assert!(list.entries.borrow().is_empty());
+        assert!(list.entries.borrow().capacity() >= 64);
+    }
+
+    #[test]
+    fn name_preference_picks_tvg_name_or_title() {
+        let list = parse(concat!(
+            "#EXTINF:-1 tvg-name=\"BBC1\",BBC One HD\nhttp://tv/bbc\n",
+            "#EXTINF:-1,Only Title\nhttp://tv/x\n",
+            "#EXTINF:-1 tvg-name=\"Short\",\nhttp://tv/y\n",
+        ));
+        let entries = list.entries.borrow();
+        let metadata = entries[0].metadata().unwrap();
+        assert_eq!(
+            (metadata.tvg_name(), metadata.title.as_str()),
+            (Some("BBC1"), "BBC One HD")
+        );
+        let names = |preference| -> Vec<_> {
+            entries.iter().map(|e| e.display_name_with(preference)).collect()
+        };
+        assert_eq!(names(NamePreference::Title), ["BBC One HD", "Only Title", "Short"]);
+        assert_eq!(names(NamePreference::ShortName), ["BBC1", "Only Title", "Short"]);
+        assert_eq!(entries[0].display_name(), "BBC One HD");
+    }
+
+    #[test]
+    fn remove_matching_hands_back_what_it_took() {
+        let list = parse("a.mp3\nhttp://radio/one\nb.mp3\nhttps://radio/two\nfile:///c.mp3\n");
+        let removed = list.remove_matching(|e| !uri_is_file(e.filename()));
+        let removed: Vec<_> = removed.iter().map(|e| e.filename().into_owned()).collect();
+        assert_eq!(removed, ["http://radio/one", "https://radio/two"]);
+        assert_eq!(list.entries.borrow().len(), 3);
+        assert!(list.remove_matching(|_| false).is_empty());
+    }
+
+    #[test]
+    fn trailing_slashes_dedup_together() {
+        let list = parse("/music/rock/\n/music/rock\n/music/rock//\n/\n/music/rockabilly\n");
+        assert_eq!(list.find_duplicates(DedupStrategy::NormalizedPath), [vec![0, 1, 2]]);
+        assert!(list.find_duplicates(DedupStrategy::Filename).is_empty());
+        assert_eq!(list.dedup_by(DedupStrategy::NormalizedPath), 2);
+        assert_eq!(filenames(&list), ["/music/rock/", "/", "/music/rockabilly"]);
+    }
+
+    #[test]
+    fn sorted_indices_by_title_leaves_storage_alone() {
+        let list = parse(
+            "#EXTINF:1,Delta\nd.mp3\n#EXTINF:1,alpha\nz.mp3\nBravo.mp3\n#EXTINF:1,Delta\nd2.mp3\n",
+        );
+        let before = filenames(&list);
+        let indices = list.sorted_indices_by_title();
+        let mut titles: Vec<_> = list
+            .entries
+            .borrow()
+            .iter()
+            .map(|e| e.title_with_fallback(TitleFallback::default()))
+            .collect();
+        let permuted: Vec<_> = indices.iter().map(|&i| titles[i].clone()).collect();
+        titles.sort();
+        assert_eq!(permuted, titles);
+        assert_eq!(indices, [2, 0, 3, 1]);
+        assert_eq!(filenames(&list), before);
+    }
+
+    #[test]
+    fn total_size_falls_back_to_extbyt() {
+        let list = parse(concat!(
+            "#EXTINF:1,A\n#EXTBYT:1000\na.mp3\n",
+            "#EXTINF:1,B\n#EXTBYT:2000\nb.mp3\n",
+            "c.mp3\n",
+            "http://radio/s\n",
+        ));
+        let stub = |e: &crate::m3u::M3uEntry| match &*e.filename() {
+            "b.mp3" => Some(2500),
+            "c.mp3" => Some(300),
+            _ => None,
+        };
+        assert_eq!(list.total_size(stub), 1000 + 2500 + 300);
+        assert_eq!(list.total_size(|_| None), 3000);
+    }
+
+    #[test]
+    fn reinsert_undoes_dedup_entries_undoable() {
+        let list = parse("a.mp3\nb.mp3\na.mp3\nc.mp3\nb.mp3\na.mp3\n");
+        let before = list.entries.borrow().clone();
+        let removed = list.dedup_entries_undoable();
+        let indices: Vec<_> = removed.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [2, 4, 5]);
+        assert_eq!(filenames(&list), ["a.mp3", "b.mp3", "c.mp3"]);
+        list.reinsert(removed);
+        assert_eq!(*list.entries.borrow(), before);
+        assert_eq!(list.dedup_entries_undoable().len(), 3);
+    }
+
+    #[test]
+    fn opened_playlists_are_edited_through_playlist_ops() {
+        let dir = temp_dir("open-ops");
+        let path = dir.join("mix.m3u");
+        std::fs::write(&path, "#PLAYLIST:Mix\n#EXTINF:3,A\na.mp3\nb.mp3\na.mp3\n").unwrap();
+        let list: Box<dyn PlaylistOps> = open(path.to_str().unwrap()).unwrap();
+        assert_eq!((list.title().as_deref(), list.count()), (Some("Mix"), 3));
+        list.add_path("http://radio/s");
+        assert!(list.remove_at(1));
+        assert!(!list.remove_at(9));
+        assert_eq!(list.dedup(DedupStrategy::Filename), 1);
+        let names: Vec<_> =
+            (0..list.count()).filter_map(|i| list.entry_display_name(i)).collect();
+        assert_eq!(names, ["A", "s"]);
+        let mut out = Vec::new();
+        list.write_to(&mut out, &SaveOptions::default()).unwrap();
+        let saved = String::from_utf8(out).unwrap();
+        assert_eq!(saved, "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:3,A\na.mp3\nhttp://radio/s\n");
+    }
+
+    #[test]
+    fn equal_ignoring_order_treats_entries_as_a_multiset() {
+        let list = parse("a.mp3\nb.mp3\nb.mp3\nc.mp3\n");
+        let reversed = parse("c.mp3\nb.mp3\nb.mp3\na.mp3\n");
+        let shuffled = parse("b.mp3\n./c.mp3\na.mp3\nb.mp3\n");
+        for strategy in [DedupStrategy::Filename, DedupStrategy::NormalizedPath] {
+            assert!(list.equal_ignoring_order(&reversed, strategy), "{strategy:?}");
+        }
+        assert!(list.equal_ignoring_order(&shuffled, DedupStrategy::NormalizedPath));
+        assert!(!list.equal_ignoring_order(&shuffled, DedupStrategy::Filename));
+        let fewer_b = parse("a.mp3\nb.mp3\nc.mp3\nc.mp3\n");
+        assert!(!list.equal_ignoring_order(&fewer_b, DedupStrategy::Filename));
+        assert!(!list.equal_ignoring_order(&parse("a.mp3\n"), DedupStrategy::Filename));
+    }
+
+    #[test]
+    fn summary_line_describes_the_playlist() {
+        let list = parse(concat!(
+            "#PLAYLIST:Roadtrip\n",
+            "#EXTINF:3600,A\na.mp3\n",
+            "#EXTINF:1380,B\nb.mp3\n",
+            "#EXTINF:-1,Radio\nhttp://radio/s\n",
+        ));
+        assert_eq!(
+            list.summary_line(),
+            "Roadtrip — 3 tracks, 1h 23m, 1 of unknown length (2 local, 1 stream)"
+        );
+        assert_eq!(parse("").summary_line(), "test — 0 tracks");
+        assert_eq!(parse("a.mp3\n").summary_line(), "test — 1 track (1 local, 0 streams)");
+    }
+
+    #[test]
+    fn infer_lengths_only_probes_entries_without_one() {
+        let list =
+            parse("#EXTINF:10,Known\na.mp3\nb.mp3\n#EXTINF:-1,Radio\nhttp://radio/live\n");
+        let probed = RefCell::new(Vec::new());
+        list.infer_lengths(|fname| {
+            probed.borrow_mut().push(fname.to_string());
+            Some(5)
+        });
+        assert_eq!(*probed.borrow(), ["b.mp3"]);
+        assert_eq!(lengths(&list), [Some(10), Some(5), None]);
+        let live = list.entries.borrow()[2].metadata().unwrap();
+        assert_eq!(live.duration_kind(), DurationKind::Live);
+    }
+
+    #[test]
+    fn expand_nested_rebases_relative_entries() {
+        let list = parse("sub/inner.m3u\nlocal.mp3\n");
+        let expanded = list.expand_nested(5, loader).unwrap();
+        assert_eq!(
+            filenames(&expanded),
+            ["sub/song.mp3", "/abs/x.mp3", "http://radio/s", "deep.mp3", "local.mp3"]
+        );
+        let song = expanded.entries.borrow()[0].clone();
+        assert_eq!(expanded.resolved_path(&song).unwrap(), Path::new("/lists/sub/song.mp3"));
+    }
+
+    #[test]
+    fn expand_nested_detects_cycles() {
+        let list = M3u::parse("/lists/./self.m3u", "self.m3u\n", &ParseOptions::default());
+        let err = list.unwrap().expand_nested(10, loader).err().unwrap();
+        let PlaylistError::CircularReference { path } = err else { panic!("{err}") };
+        assert_eq!(path, "/lists/self.m3u");
+        // Running out of depth is not an error, the reference is just kept
+        let shallow = parse("sub/inner.m3u\n").expand_nested(1, loader).unwrap();
+        assert_eq!(filenames(&shallow)[3], "deep.m3u");
+    }
+
+    #[test]
+    fn title_fallback_is_picked_per_read() {
+        let text = "#EXTINF:3,Titled\na.mp3\n/music/01 - Queen - Bohemian_Rhapsody.mp3\n";
+        let titles = |title_fallback| {
+            let options = ParseOptions { title_fallback, ..Default::default() };
+            let list = M3u::parse("/lists/test.m3u", text, &options).unwrap();
+            (list.entry_title(0).unwrap(), list.entry_title(1).unwrap())
+        };
+        let (titled, stem) = titles(TitleFallback::Stem);
+        assert_eq!(titled, "Titled");
+        assert_eq!(stem, "01 - Queen - Bohemian_Rhapsody");
+        let (titled, raw) = titles(TitleFallback::Filename);
+        assert_eq!(titled, "Titled");
+        assert_eq!(raw, "/music/01 - Queen - Bohemian_Rhapsody.mp3");
+        assert_eq!(titles(TitleFallback::ArtistTrack).1, "Queen - Bohemian Rhapsody");
+        assert_eq!(titles(TitleFallback::Empty).1, "");
+        assert_eq!(parse(text).entry_title(2), None);
+    }
+
+    #[test]
+    fn artist_track_titles_come_from_the_stem() {
+        assert_eq!(
+            artist_track("07. Daft_Punk - One More Time").unwrap(),
+            "Daft Punk - One More Time"
+        );
+        assert_eq!(artist_track("2Pac - Changes").unwrap(), "2Pac - Changes");
+        assert_eq!(artist_track("Intro"), None);
+        assert_eq!(artist_track("01 - Untitled"), None);
+    }
+
+    #[test]
+    fn vlc_file_uris_decode_to_local_paths() {
+        let vlc = "file:///C:/Music/a%20b.mp3";
+        assert_eq!(local_path(vlc).unwrap(), Path::new("C:/Music/a b.mp3"));
+        assert_eq!(file_uri(local_path(vlc).unwrap()).unwrap(), vlc);
+        assert_eq!(
+            file_uri("/music/Queen & Co.mp3").unwrap(),
+            "file:///music/Queen%20%26%20Co.mp3"
+        );
+        assert_eq!(file_uri("music/a.mp3"), None);
+        let list = parse("#EXTINF:3,A\nfile:///C:/Music/a%20b.mp3\n");
+        assert_eq!(list.entries.borrow()[0].fname, vlc);
+    }
+
+    #[test]
+    fn file_uris_respect_their_host() {
+        let path = |uri| local_path(uri).map(|p| p.to_string_lossy().into_owned());
+        assert_eq!(path("file://localhost/etc/x.mp3").as_deref(), Some("/etc/x.mp3"));
+        assert_eq!(path("FILE://LocalHost/etc/x.mp3").as_deref(), Some("/etc/x.mp3"));
+        assert_eq!(path("file:/music/x.mp3").as_deref(), Some("/music/x.mp3"));
+        assert_eq!(path("file://C:/Music/x.mp3").as_deref(), Some("C:/Music/x.mp3"));
+        assert_eq!(path("file://nas/share/x.mp3"), None);
+        assert_eq!(path("http://localhost/x.mp3"), None);
+        assert_eq!(path("music/x.mp3").as_deref(), Some("music/x.mp3"));
+    }
+
+    #[test]
+    fn find_duplicates_groups_every_cluster() {
+        let list = parse("a.mp3\nb.mp3\nx/../a.mp3\nc.mp3\nb.mp3\na.mp3\n");
+        assert_eq!(list.find_duplicates(DedupStrategy::Filename), [vec![0, 5], vec![1, 4]]);
+        let normalized = list.find_duplicates(DedupStrategy::NormalizedPath);
+        assert_eq!(normalized, [vec![0, 2, 5], vec![1, 4]]);
+        assert_eq!(list.find_duplicates(DedupStrategy::Exact), [vec![0, 5], vec![1, 4]]);
+        assert_eq!(list.count(), 6);
+    }
+
+    #[test]
+    fn find_duplicates_scales_to_large_playlists() {
+        let text: String = (0..50_000).map(|i| format!("{}.mp3\n", i % 25_000)).collect();
+        let groups = parse(&text).find_duplicates(DedupStrategy::Filename);
+        assert_eq!(groups.len(), 25_000);
+        assert_eq!(groups[1], [1, 25_001]);
+    }
+
+    #[test]
+    fn common_base_dir_finds_the_shared_directory() {
+        let list = parse("/music/rock/a.mp3\n/music/jazz/b/c.mp3\nhttp://radio/s\n");
+        assert_eq!(list.common_base_dir().unwrap(), Path::new("/music"));
+        let relative = parse("album/1.mp3\nalbum/2.mp3\n");
+        assert_eq!(relative.common_base_dir().unwrap(), Path::new("/lists/album"));
+        let windows = parse("C:\\Music\\a\\c.mp3\nC:/Music/b/d.mp3\n");
+        assert_eq!(windows.common_base_dir().unwrap(), Path::new("C:/Music"));
+        assert_eq!(parse("http://radio/s\n").common_base_dir(), None);
+    }
+
+    #[test]
+    fn common_base_dir_is_none_across_roots() {
+        assert_eq!(parse("C:\\a\\c.mp3\nD:\\b\\e.mp3\n").common_base_dir(), None);
+        assert_eq!(parse("C:\\a\\c.mp3\nC:\\b\\e.mp3\n").common_base_dir(), None);
+        assert_eq!(parse("/a/c.mp3\n/b/e.mp3\n").common_base_dir(), None);
+        assert_eq!(parse("/a/c.mp3\nC:\\a\\c.mp3\n").common_base_dir(), None);
+    }
+
+    #[test]
+    fn extensions_come_from_the_path() {
+        let list = parse(concat!(
+            "/music/Track.FLAC\n",
+            "http://radio/a.mp3?v=1&fmt=ogg\n",
+            "file:///x/y.flac#t=10\n",
+            "file:///x/y.flac?v=1\n",
+            "C:\\Music\\a#1.mp3\n",
+            "/music/README\n",
+            "http://radio.example/stream\n",
+        ));
+        let extensions: Vec<_> = list.entries.borrow().iter().map(Entry::extension).collect();
+        let expected = [Some("flac"), Some("mp3"), Some("flac"), Some("flac"), Some("mp3")];
+        assert_eq!(extensions[..5], expected.map(|ext| ext.map(str::to_string)));
+        assert_eq!(extensions[5..], [None, None]);
+    }
+
+    #[test]
+    fn filter_played_between_keeps_the_window() {
+        let list = parse(concat!(
+            "#EXTINF:3,Morning\n#EXT-ABS-PLAYED:1000\nmorning.mp3\n",
+            "#EXT-ABS-PLAYED:2000\n#EXTINF:3,Noon\nnoon.mp3\n",
+            "never.mp3\n",
+            "#EXT-ABS-PLAYED:2999\nlate.mp3\n",
+            "#EXT-ABS-PLAYED:3000\nnight.mp3\n",
+        ));
+        let at = |seconds| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+        let played = |start, end| filenames(&list.filter_played_between(at(start), at(end)));
+        assert_eq!(played(2000, 3000), ["noon.mp3", "late.mp3"]);
+        assert_eq!(played(0, 10_000), ["morning.mp3", "noon.mp3", "late.mp3", "night.mp3"]);
+        assert!(played(3001, 10_000).is_empty());
+        assert_eq!(
+            list.filter_played_between(at(0), at(1)).info.borrow().fname,
+            "/lists/test.m3u"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn canonicalize_paths_resolves_symlinks() {
+        let dir = std::fs::canonicalize(temp_dir("canonicalize")).unwrap();
+        std::fs::create_dir(dir.join("real")).unwrap();
+        std::fs::write(dir.join("real/a.mp3"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+        let uri = file_uri(dir.join("link/a.mp3")).unwrap();
+        let text = format!(
+            "link/a.mp3\n./real/../link/a.mp3\nFILE{}\nlink/missing.mp3\nhttp://radio/s\n",
+            &uri[4..]
+        );
+        let list = M3u::parse("/lists/test.m3u", &text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.canonicalize_paths(&dir).unwrap(), 3);
+        let real = dir.join("real/a.mp3").to_string_lossy().into_owned();
+        let real_uri = file_uri(dir.join("real/a.mp3")).unwrap();
+        assert_eq!(
+            filenames(&list),
+            [real.as_str(), &real, &real_uri, "link/missing.mp3", "http://radio/s"]
+        );
+        assert_eq!(list.canonicalize_paths(&dir).unwrap(), 0);
+    }
 }
@@ -7,4 +7,2014 @@
 //! playlist files to further the possibilities that Absinthium can handle.
 
 use super::*;
-use plaintext::*
+use crate::registry::FormatRegistry;
+use std::{
+    io::{Read, Write},
+    time::UNIX_EPOCH,
+};
+
+/// The marker written by [`SaveOptions::end_marker`], and recognized on reading as
+/// [`M3uInfo::end_marker`].
+pub const END_MARKER: &str = "#EXT-ABS-ENDLIST";
+
+/// The directive marking all `#EXTINF` durations after it as milliseconds, see
+/// [`M3uInfo::millisecond_durations`].
+pub const UNIT_MS: &str = "#EXT-ABS-UNIT:ms";
+
+/// The directive recording when an entry was last played, see [`M3uMetadata::played_at`].
+pub const PLAYED: &str = "#EXT-ABS-PLAYED:";
+
+/// Parse the duration field of an `#EXTINF` directive into seconds.
+///
+/// The format calls for a plain number of seconds, with `-1` meaning the length is
+/// unknown (usually because it's a live stream). Hand-edited files sometimes use a clock
+/// format instead, so `MM:SS` and `HH:MM:SS` are accepted as well. An unknown length is
+/// returned as [`None`].
+///
+/// HLS and plenty of exporters give fractional lengths like `9.009`. These are rounded to
+/// the nearest second, with halves rounding up. Use [`ParseOptions::keep_raw_extinf`] to
+/// write the original value back out.
+///
+/// Some files also use `0` to mean unknown, but others use it for entries that really are
+/// that short. This returns it as `Some(0)`, see [`ParseOptions::zero_length_unknown`] to
+/// change that when reading whole playlists.
+pub fn parse_duration(value: &str) -> Result<Option<u32>, PlaylistError> {
+    let value = value.trim();
+    if value == "-1" {
+        return Ok(None);
+    }
+    let invalid = || PlaylistError::Parse {
+        line: None,
+        message: format!("invalid #EXTINF duration `{value}`"),
+    };
+    let (whole, round_up) = match value.split_once('.') {
+        Some((whole, fraction))
+            if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            (whole, fraction.as_bytes()[0] >= b'5')
+        }
+        Some(_) => return Err(invalid()),
+        None => (value, false),
+    };
+    let mut parts = whole.split(':');
+    let mut seconds: u32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+    let mut fields = 1;
+    for part in parts {
+        fields += 1;
+        let unit: u32 = part.parse().map_err(|_| invalid())?;
+        if fields > 3 || unit >= 60 {
+            return Err(invalid());
+        }
+        seconds =
+            seconds.checked_mul(60).and_then(|s| s.checked_add(unit)).ok_or_else(invalid)?;
+    }
+    seconds.checked_add(u32::from(round_up)).map(Some).ok_or_else(invalid)
+}
+
+/// The two kinds of playlist HLS (`.m3u8`) streaming uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HlsKind {
+    /// Lists the available variant streams, each through an `#EXT-X-STREAM-INF`
+    Master,
+    /// Lists the actual segments of a stream and has an `#EXT-X-TARGETDURATION`
+    Media,
+}
+
+/// Check whether the text of an m3u file is an HLS playlist, and if so which kind.
+///
+/// Master playlists are recognized by their `#EXT-X-STREAM-INF` directives, media
+/// playlists by the `#EXT-X-TARGETDURATION` they're required to have. Anything else is
+/// treated as a regular playlist and returns [`None`].
+pub fn hls_kind(text: &str) -> Option<HlsKind> {
+    let mut kind = None;
+    for line in text.lines().map(str::trim_start) {
+        if strip_directive(line, "#EXT-X-STREAM-INF").is_some() {
+            return Some(HlsKind::Master);
+        } else if strip_directive(line, "#EXT-X-TARGETDURATION").is_some() {
+            kind = Some(HlsKind::Media);
+        }
+    }
+    kind
+}
+
+/// Parse the value of an `#EXTINF` directive, everything after the colon.
+///
+/// This is the duration according to [`parse_duration`], followed by any number of
+/// `key="value"` attributes, a comma, and then the title. Attribute values don't have to
+/// be quoted as long as they don't contain any spaces or commas.
+pub fn parse_extinf(value: &str) -> Result<M3uMetadata, PlaylistError> {
+    let end = value.find([' ', '\t', ',']).unwrap_or(value.len());
+    let mut metadata = M3uMetadata {
+        duration: parse_duration(&value[..end])?,
+        live: value[..end].trim() == "-1",
+        ..Default::default()
+    };
+    let rest = parse_attributes(&value[end..], &mut metadata.attributes);
+    // Whatever follows the next comma is the title, even if some junk came before it
+    metadata.title =
+        rest.split_once(',').map(|(_, title)| title.trim().to_string()).unwrap_or_default();
+    Ok(metadata)
+}
+
+/// Parse `key="value"` attributes from the start of `text` into `attributes`.
+///
+/// Returns the remainder of `text`, starting at the first thing that isn't an attribute.
+fn parse_attributes<'t>(mut text: &'t str, attributes: &mut Vec<(String, String)>) -> &'t str {
+    loop {
+        text = text.trim_start();
+        let Some((key, after)) = text
+            .split_once('=')
+            .filter(|(k, _)| !k.is_empty() && !k.contains([',', ' ', '\t']))
+        else {
+            return text;
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_at(after.find([' ', '\t', ',']).unwrap_or(after.len())),
+        };
+        set_attribute(attributes, key.to_string(), value.to_string());
+        text = after;
+    }
+}
+
+/// Turn an entry line into the path to store, handling [`ParseOptions::unquote_paths`] and
+/// [`ParseOptions::normalizer`].
+fn entry_path<'t>(line: &'t str, options: &ParseOptions) -> Cow<'t, str> {
+    let path = unquote_path(line, options);
+    match &options.normalizer {
+        Some(normalizer) => Cow::Owned(normalizer.apply(&path)),
+        None => path,
+    }
+}
+
+/// Get the value of the `href` or `src` attribute on an XML-ish tag like `<ref href="...">`,
+/// with the predefined XML entities like `&amp;` decoded.
+fn xml_ref(line: &str) -> Option<Cow<'_, str>> {
+    let lowercase = line.to_ascii_lowercase();
+    let start = ["href=", "src="].iter().find_map(|name| {
+        lowercase
+            .match_indices(name)
+            .find(|(i, _)| lowercase[..*i].ends_with(char::is_whitespace))
+            .map(|(i, _)| i + name.len())
+    })?;
+    let value = &line[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|c: char| c.is_whitespace() || c == '>').next()?,
+    };
+    if value.is_empty() {
+        return None;
+    }
+    if !value.contains('&') {
+        return Some(Cow::Borrowed(value));
+    }
+    let entities = [("&lt;", "<"), ("&gt;", ">"), ("&quot;", "\""), ("&apos;", "'")];
+    let decoded = entities.iter().fold(value.to_string(), |v, (from, to)| v.replace(from, to));
+    // Last, so an escaped entity like `&amp;lt;` isn't decoded twice
+    Some(Cow::Owned(decoded.replace("&amp;", "&")))
+}
+
+/// Strip the quotes from an entry line wrapped in them, if enabled through
+/// [`ParseOptions::unquote_paths`]. Quotes inside of it can be escaped as `\"`.
+///
+/// The last quote on the line always closes the path, so a backslash right in front of
+/// it is part of the path rather than an escape. That way `"C:\Music\"` is read as
+/// `C:\Music\`.
+fn unquote_path<'t>(line: &'t str, options: &ParseOptions) -> Cow<'t, str> {
+    match line.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(path) if options.unquote_paths => {
+            if path.contains("\\\"") {
+                Cow::Owned(path.replace("\\\"", "\""))
+            } else {
+                Cow::Borrowed(path)
+            }
+        }
+        _ => Cow::Borrowed(line),
+    }
+}
+
+/// Strip the directive `name` from the start of `line`, ignoring the case of the name.
+///
+/// Some writers get creative with directives like `#Extinf`, but their values are left
+/// untouched, so attributes and titles keep their case.
+pub(crate) fn strip_directive<'t>(line: &'t str, name: &str) -> Option<&'t str> {
+    line.get(..name.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(name))
+        .map(|_| &line[name.len()..])
+}
+
+/// Check whether a filename is a `file://` URI, in any case.
+fn is_file_uri(fname: &str) -> bool {
+    fname.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("file://"))
+}
+
+/// Look up the value of an attribute in a list of them.
+fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+/// Set an attribute in a list of them, replacing the value if it's already present.
+fn set_attribute(attributes: &mut Vec<(String, String)>, key: String, value: String) {
+    match attributes.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => attributes.push((key, value)),
+    }
+}
+
+/// Parse a `tvg-shift` value, the EPG timeshift in (possibly negative) hours.
+fn parse_tvg_shift(value: &str) -> Option<i32> {
+    value.trim().parse().ok()
+}
+
+/// Displays a list of attributes the way they appear on a directive, each with a leading
+/// space.
+struct Attributes<'a>(&'a [(String, String)]);
+
+impl fmt::Display for Attributes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.0 {
+            write!(f, " {key}=\"{value}\"")?;
+        }
+        Ok(())
+    }
+}
+
+/// An EXT-M3U playlist.
+pub type M3u<'a> = Playlist<M3uInfo, M3uMetadata, M3uEntry<'a>>;
+
+/// Playlist-level info for an EXT-M3U playlist.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct M3uInfo {
+    /// The playlist title, as set through `#PLAYLIST`
+    pub title: Option<String>,
+    /// The filename or URI the playlist is found at
+    pub fname: String,
+    /// The `key="value"` attributes on the `#EXTM3U` header, in order
+    pub attributes: Vec<(String, String)>,
+    /// Whether the file gives `#EXTINF` durations in milliseconds, as flagged by an
+    /// `#EXT-ABS-UNIT:ms` directive. Durations are always stored in seconds, rounded to the
+    /// nearest one, and only converted back when writing.
+    pub millisecond_durations: bool,
+    /// Whether the file ended in an [`END_MARKER`]. A file that was saved with
+    /// [`SaveOptions::end_marker`] but doesn't have one got cut off somewhere. Whether
+    /// the marker is written again only depends on the save options.
+    pub end_marker: bool,
+    /// The fallback for entries without a title, from [`ParseOptions::title_fallback`]
+    pub title_fallback: TitleFallback,
+    /// The number entries are counted from, from [`ParseOptions::numbering_base`]
+    pub numbering_base: NumberingBase,
+    /// A `#!` line the file started with, kept verbatim so scripted playlists stay
+    /// runnable. It's written back as the very first line, before `#EXTM3U`.
+    pub shebang: Option<String>,
+    /// `#EXT-X-*` lines found before the first entry, kept verbatim if
+    /// [`ParseOptions::keep_hls_directives`] is set. They're written right after the
+    /// header.
+    pub directives: Vec<String>,
+    /// `#EXT-X-*` lines found after the last entry, like `#EXT-X-ENDLIST`, kept the same
+    /// way and written after all entries.
+    pub trailing_directives: Vec<String>,
+}
+
+impl M3uInfo {
+    /// Get the value of an `#EXTM3U` header attribute, if present.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        attribute(&self.attributes, key)
+    }
+
+    /// Set an `#EXTM3U` header attribute, replacing the value if it's already present.
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        set_attribute(&mut self.attributes, key.into(), value.into())
+    }
+
+    /// The playlist-wide EPG timeshift in hours, from the `tvg-shift` header attribute.
+    pub fn tvg_shift(&self) -> Option<i32> {
+        self.attribute("tvg-shift").and_then(parse_tvg_shift)
+    }
+}
+
+impl PlaylistInfo for M3uInfo {
+    fn title(&self) -> Option<impl Deref<Target = str>> {
+        self.title.as_deref()
+    }
+
+    fn filename(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.fname)
+    }
+
+    fn title_fallback(&self) -> TitleFallback {
+        self.title_fallback
+    }
+
+    fn numbering_base(&self) -> NumberingBase {
+        self.numbering_base
+    }
+}
+
+/// A single entry in an EXT-M3U playlist.
+///
+/// Comparisons ignore the [`num`](Self::num) and [`span`](Self::span), which only say
+/// where in a playlist the entry is. Two entries are equal if they point to the same file
+/// with the same metadata.
+#[derive(Clone, Debug, Default)]
+pub struct M3uEntry<'a> {
+    pub num: u32,
+    pub fname: Cow<'a, str>,
+    pub metadata: RefCell<Option<M3uMetadata>>,
+    /// The byte range of the text the entry was parsed from, see [`Entry::source_span`]
+    pub span: Option<Range<usize>>,
+}
+
+impl<'a> M3uEntry<'a> {
+    /// Create an entry numbered `num`, parsed from `span` if it was parsed at all.
+    fn new(
+        num: u32,
+        fname: Cow<'a, str>,
+        metadata: Option<M3uMetadata>,
+        span: Option<Range<usize>>,
+    ) -> Self {
+        Self { num, fname, metadata: RefCell::new(metadata), span }
+    }
+
+    /// Deep-copy the entry into one that doesn't borrow from anything.
+    pub fn clone_owned(&self) -> M3uEntry<'static> {
+        M3uEntry {
+            num: self.num,
+            fname: Cow::Owned(self.fname.to_string()),
+            metadata: self.metadata.clone(),
+            span: self.span.clone(),
+        }
+    }
+}
+
+impl PartialEq for M3uEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fname == other.fname && self.metadata == other.metadata
+    }
+}
+
+impl Entry<M3uMetadata> for M3uEntry<'_> {
+    fn entry_num(&self) -> u32 {
+        self.num
+    }
+
+    fn filename(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.fname)
+    }
+
+    fn set_entry_num(&mut self, num: u32) {
+        self.num = num;
+    }
+
+    fn set_filename(&mut self, fname: String) {
+        self.fname = Cow::Owned(fname);
+    }
+
+    fn metadata(&self) -> Option<M3uMetadata> {
+        self.metadata.try_borrow().ok().and_then(|m| m.clone())
+    }
+
+    /// Replaces the currently stored metadata
+    ///
+    /// ## Panics
+    /// As this uses [`RefCell::replace`] under the hood, this will panic if there's
+    /// active borrows of the inner Metadata object (though there shouldn't be).
+    fn write_metadata(&self, metadata: M3uMetadata) {
+        self.metadata.replace(Some(metadata)).map(drop).unwrap_or_default()
+    }
+
+    /// Covers the entry's directives and its path, from the first line that belongs to it
+    /// up to the end of the path. This is never updated, so it goes stale once the entry
+    /// is edited.
+    fn source_span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+}
+
+/// Writes the entry the way it should appear in the playlist file, directives included.
+impl fmt::Display for M3uEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(metadata) = self.metadata().map(|m| m.to_string())
+            && !metadata.is_empty()
+        {
+            writeln!(f, "{metadata}")?;
+        }
+        write!(f, "{}", self.fname)
+    }
+}
+
+/// What the duration of an entry is known to be, see [`M3uMetadata::duration_kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DurationKind {
+    /// The entry has a length, in seconds
+    Known(u32),
+    /// The entry is a live stream, marked with a duration of `-1`
+    Live,
+    /// The length of the entry was never recorded
+    Unknown,
+}
+
+/// Everything the `#EXTINF`, `#EXTGRP`, `#EXTBYT` and [`PLAYED`] directives can tell us
+/// about an entry.
+///
+/// Comparisons ignore [`raw_extinf`](Self::raw_extinf) and
+/// [`comment_title`](Self::comment_title), since they only affect formatting.
+#[derive(Clone, Debug, Default)]
+pub struct M3uMetadata {
+    /// Length in seconds, [`None`] if unknown (written as `-1`)
+    pub duration: Option<u32>,
+    /// Whether the `#EXTINF` line explicitly gave `-1` as the duration, which is what
+    /// live streams use. See [`duration_kind`](Self::duration_kind).
+    pub live: bool,
+    /// The display title, everything after the comma on the `#EXTINF` line
+    pub title: String,
+    /// The group set through `#EXTGRP`
+    pub group: Option<String>,
+    /// The `key="value"` attributes in between the duration and the title, in order
+    pub attributes: Vec<(String, String)>,
+    /// The file size in bytes, as set through `#EXTBYT`
+    pub size: Option<u64>,
+    /// When the entry was last played, as set through an `#EXT-ABS-PLAYED` directive
+    /// holding the seconds since the Unix epoch. Other players don't know this directive,
+    /// but it lets history playlists written by Absinthium keep their play times.
+    pub played_at: Option<SystemTime>,
+    /// `#EXTVLCOPT` options that come before the `#EXTINF` line, in order
+    pub vlc_options_before: Vec<String>,
+    /// `#EXTVLCOPT` options that come after the `#EXTINF` line, in order
+    pub vlc_options: Vec<String>,
+    /// Verbatim `#EXT-X-*` lines that come before the `#EXTINF` line, if
+    /// [`ParseOptions::keep_hls_directives`] is set
+    pub directives_before: Vec<String>,
+    /// Verbatim `#EXT-X-*` lines that come after the `#EXTINF` line, like
+    /// `#EXT-X-BYTERANGE`
+    pub directives: Vec<String>,
+    /// The `#EXTINF` line exactly as it was read, surrounding whitespace included, if
+    /// [`ParseOptions::keep_raw_extinf`] is set. It's written back as-is for as long as it
+    /// still says the same thing as the fields above, so untouched entries keep their
+    /// original formatting.
+    pub raw_extinf: Option<String>,
+    /// Whether the title came from a comment line, as read with
+    /// [`ParseOptions::comment_titles`]. For as long as there's no duration or attributes
+    /// to write, the title is written back as a `# Title` comment, since an `#EXTINF` line
+    /// would need a made up `-1` and turn the entry into a live stream.
+    pub comment_title: bool,
+}
+
+impl PartialEq for M3uMetadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.duration == other.duration
+            && self.live == other.live
+            && self.title == other.title
+            && self.group == other.group
+            && self.size == other.size
+            && self.played_at == other.played_at
+            && self.attributes == other.attributes
+            && self.vlc_options_before == other.vlc_options_before
+            && self.vlc_options == other.vlc_options
+            && self.directives_before == other.directives_before
+            && self.directives == other.directives
+    }
+}
+
+impl M3uMetadata {
+    /// Get [`raw_extinf`](Self::raw_extinf) if it still matches the fields.
+    fn unchanged_raw_extinf(&self) -> Option<&str> {
+        let raw = self.raw_extinf.as_deref()?;
+        let parsed = parse_extinf(strip_directive(raw.trim(), "#EXTINF:")?).ok()?;
+        (parsed.duration == self.duration
+            && parsed.live == self.live
+            && parsed.title == self.title
+            && parsed.attributes == self.attributes)
+            .then_some(raw)
+    }
+
+    /// Whether any of the written fields contain a line break, which would split them up.
+    fn has_line_breaks(&self) -> bool {
+        let breaks = |text: &str| text.contains(['\n', '\r']);
+        breaks(&self.title)
+            || self.group.as_deref().is_some_and(breaks)
+            || self.attributes.iter().any(|(key, value)| breaks(key) || breaks(value))
+            || [
+                &self.directives_before,
+                &self.vlc_options_before,
+                &self.directives,
+                &self.vlc_options,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|line| breaks(line))
+    }
+
+    /// Whether an `#EXTINF` line is written for this metadata at all.
+    fn writes_extinf(&self) -> bool {
+        self.duration_kind() != DurationKind::Unknown
+            || (!self.title.is_empty() && !self.comment_title)
+            || !self.attributes.is_empty()
+    }
+
+    /// The `#EXTINF` line up to the comma in front of the title.
+    fn extinf_prefix(&self) -> String {
+        match self.duration {
+            Some(duration) => format!("#EXTINF:{duration}{}", Attributes(&self.attributes)),
+            None => format!("#EXTINF:-1{}", Attributes(&self.attributes)),
+        }
+    }
+
+    /// Tell a live stream apart from an entry that simply has no length recorded.
+    ///
+    /// [`len`](EntryMetadata::len) returns [`None`] for both, this returns
+    /// [`DurationKind::Live`] only if the `#EXTINF` line said `-1`.
+    pub fn duration_kind(&self) -> DurationKind {
+        match self.duration {
+            Some(seconds) => DurationKind::Known(seconds),
+            None if self.live => DurationKind::Live,
+            None => DurationKind::Unknown,
+        }
+    }
+
+    /// Get the value of an `#EXTINF` attribute, if present.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        attribute(&self.attributes, key)
+    }
+
+    /// Set an `#EXTINF` attribute, replacing the value if it's already present.
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        set_attribute(&mut self.attributes, key.into(), value.into())
+    }
+
+    /// The short channel name from the `tvg-name` attribute, if present.
+    ///
+    /// This is often different from [`title`](Self::title), the text after the comma.
+    pub fn tvg_name(&self) -> Option<&str> {
+        self.attribute("tvg-name")
+    }
+
+    /// The EPG timeshift in hours for this entry, from the `tvg-shift` attribute.
+    ///
+    /// This overrides the playlist-wide [`M3uInfo::tvg_shift`] where present.
+    pub fn tvg_shift(&self) -> Option<i32> {
+        self.attribute("tvg-shift").and_then(parse_tvg_shift)
+    }
+}
+
+impl EntryMetadata for M3uMetadata {
+    fn title(&self) -> impl Deref<Target = str> + PartialEq {
+        self.title.as_str()
+    }
+
+    fn len(&self) -> Option<u32> {
+        self.duration
+    }
+
+    /// Setting a length also clears [`live`](Self::live), since live streams have none.
+    fn set_len(&mut self, len: Option<u32>) {
+        self.live &= len.is_none();
+        self.duration = len;
+    }
+
+    fn is_live(&self) -> bool {
+        self.duration_kind() == DurationKind::Live
+    }
+
+    fn info(&self) -> impl Deref<Target = str> + PartialEq {
+        self.to_string()
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn played_at(&self) -> Option<SystemTime> {
+        self.played_at
+    }
+
+    /// Returns the IPTV-style `tvg-name` attribute.
+    fn short_name(&self) -> Option<impl Deref<Target = str>> {
+        self.tvg_name()
+    }
+
+    /// Returns the `#EXTGRP` group, falling back to the IPTV-style `group-title` attribute.
+    fn group(&self) -> Option<impl Deref<Target = str>> {
+        self.group.as_deref().or_else(|| self.attribute("group-title"))
+    }
+}
+
+/// Writes the `#EXTINF` line, followed by `#EXTGRP`, `#EXTBYT` and [`PLAYED`] lines if the
+/// entry has a group, a size or a play time. Play times before the Unix epoch are dropped.
+///
+/// The duration is written as `-1` for live streams. An entry whose length is
+/// [`Unknown`](DurationKind::Unknown) only gets an `#EXTINF` line if there's a title or
+/// attributes to put on it, so metadata holding nothing but a group doesn't invent one. A
+/// [`comment_title`](M3uMetadata::comment_title) without either is written as a comment.
+///
+/// `#EXTVLCOPT` lines are written on whichever side of the `#EXTINF` line they were found,
+/// since some players are picky about it, and so are kept `#EXT-X-*` lines, which come
+/// before the `#EXTVLCOPT` lines or right after the `#EXTINF` line. Only that side is
+/// kept, not their place among the other directives: `#EXTGRP` and `#EXTBYT` always go
+/// after the `#EXTINF` line, ahead of any `#EXTVLCOPT` lines following it.
+///
+/// A width pads everything before the title with spaces, which readers skip over, so
+/// `{:40}` lines up the titles of shorter lines. Without one, an unchanged
+/// [`raw_extinf`](M3uMetadata::raw_extinf) is written instead.
+impl fmt::Display for M3uMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        for directive in &self.directives_before {
+            write!(f, "{sep}{directive}")?;
+            sep = "\n";
+        }
+        for option in &self.vlc_options_before {
+            write!(f, "{sep}#EXTVLCOPT:{option}")?;
+            sep = "\n";
+        }
+        let skip_extinf = !self.writes_extinf();
+        match (f.width(), self.unchanged_raw_extinf()) {
+            _ if skip_extinf && !self.title.is_empty() => {
+                write!(f, "{sep}# {}", self.title)?;
+                sep = "\n";
+            }
+            _ if skip_extinf => {}
+            (None, Some(raw)) => {
+                write!(f, "{sep}{raw}")?;
+                sep = "\n";
+            }
+            (width, _) => {
+                let width = width.unwrap_or(0);
+                write!(f, "{sep}{:width$},{}", self.extinf_prefix(), self.title)?;
+                sep = "\n";
+            }
+        }
+        for directive in &self.directives {
+            write!(f, "{sep}{directive}")?;
+            sep = "\n";
+        }
+        if let Some(group) = &self.group {
+            write!(f, "{sep}#EXTGRP:{group}")?;
+            sep = "\n";
+        }
+        if let Some(size) = self.size {
+            write!(f, "{sep}#EXTBYT:{size}")?;
+            sep = "\n";
+        }
+        if let Some(played) = self.played_at.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+            write!(f, "{sep}{PLAYED}{}", played.as_secs())?;
+            sep = "\n";
+        }
+        for option in &self.vlc_options {
+            write!(f, "{sep}#EXTVLCOPT:{option}")?;
+            sep = "\n";
+        }
+        Ok(())
+    }
+}
+
+/// Chainable construction of an [`M3uEntry`].
+///
+/// Setting any of the metadata fields gives the entry metadata, leaving them all alone
+/// produces a bare entry that is written as just its path.
+#[derive(Clone, Debug, Default)]
+pub struct M3uEntryBuilder<'a> {
+    num: u32,
+    fname: Cow<'a, str>,
+    metadata: Option<M3uMetadata>,
+}
+
+impl<'a> M3uEntryBuilder<'a> {
+    /// Start building an entry pointing to the given path or URI.
+    pub fn new(fname: impl Into<Cow<'a, str>>) -> Self {
+        Self { fname: fname.into(), ..Default::default() }
+    }
+
+    /// Set the entry number.
+    pub fn num(mut self, num: u32) -> Self {
+        self.num = num;
+        self
+    }
+
+    /// Set the display title.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.metadata.get_or_insert_default().title = title.into();
+        self
+    }
+
+    /// Set the length in seconds.
+    pub fn duration(mut self, seconds: u32) -> Self {
+        self.metadata.get_or_insert_default().duration = Some(seconds);
+        self
+    }
+
+    /// Set the `#EXTGRP` group.
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.metadata.get_or_insert_default().group = Some(group.into());
+        self
+    }
+
+    /// Add an `#EXTINF` attribute, replacing the value if the key was already set.
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.get_or_insert_default().set_attribute(key, value);
+        self
+    }
+
+    /// Produce the entry.
+    pub fn build(self) -> M3uEntry<'a> {
+        M3uEntry::new(self.num, self.fname, self.metadata, None)
+    }
+}
+
+impl<'a> M3u<'a> {
+    /// Parse the text of an M3U or EXT-M3U playlist found at `fname`.
+    ///
+    /// All extended directives are optional, so plain file listings parse just fine.
+    /// Entries borrow their filenames from `text` where possible, and remember which bytes
+    /// of it they came from as their [`source_span`](Entry::source_span). Comments and
+    /// directives that aren't supported are skipped, except for `#EXT-X-*` ones if
+    /// [`ParseOptions::keep_hls_directives`] asks for them.
+    ///
+    /// A `#!` first line is no directive, it's kept as the [`shebang`](M3uInfo::shebang)
+    /// instead.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Parse`] for malformed `#EXTINF`, `#EXTBYT` and [`PLAYED`]
+    /// lines and for lines longer than [`ParseOptions::max_line_len`].
+    /// The reported line numbers count lines in `text` as given, so a byte order mark or
+    /// `\r\n` line endings don't shift them.
+    pub fn parse(
+        fname: impl Into<String>,
+        text: &'a str,
+        options: &ParseOptions,
+    ) -> Result<Self, PlaylistError> {
+        let mut info = M3uInfo {
+            fname: fname.into(),
+            title_fallback: options.title_fallback,
+            numbering_base: options.numbering_base,
+            ..Default::default()
+        };
+        let mut entries = Vec::new();
+        let mut pending: Option<M3uMetadata> = None;
+        // Whether the pending metadata has seen its `#EXTINF` yet
+        let mut seen_extinf = false;
+        let base = options.numbering_base;
+        // The comment on the line before, if it could be a title for this one
+        let mut comment_title: Option<(&str, usize)> = None;
+        let mut previous_comment = false;
+        // Whether the durations from here on are in milliseconds
+        let mut milliseconds = false;
+        // Where the entry being read started, as a byte offset into `text`
+        let mut entry_start = None;
+        let source = text;
+        let offset = |part: &str| part.as_ptr() as usize - source.as_ptr() as usize;
+        let span = |start: Option<usize>, part: &str| {
+            Some(start.unwrap_or(offset(part))..offset(part) + part.len())
+        };
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        for (index, line) in text.lines().enumerate() {
+            if line.len() > options.max_line_len {
+                return Err(PlaylistError::Parse {
+                    line: Some(index + 1),
+                    message: format!("line is longer than {} bytes", options.max_line_len),
+                });
+            }
+            if index == 0 && line.starts_with("#!") {
+                info.shebang = Some(line.to_string());
+                continue;
+            }
+            let (raw, line) = (line, line.trim());
+            let after_comment = std::mem::take(&mut previous_comment);
+            if let Some((title, start)) = comment_title.take()
+                && !seen_extinf
+                && !line.is_empty()
+                && !line.starts_with('#')
+            {
+                let metadata = pending.get_or_insert_default();
+                metadata.title = title.to_string();
+                metadata.comment_title = true;
+                entry_start.get_or_insert(start);
+            }
+            if let Some(extinf) = strip_directive(line, "#EXTINF:") {
+                let mut metadata = parse_extinf(extinf).map_err(|e| e.at_line(index + 1))?;
+                if options.keep_raw_extinf {
+                    metadata.raw_extinf = Some(raw.to_string());
+                }
+                if milliseconds {
+                    metadata.duration =
+                        metadata.duration.map(|ms| ms / 1000 + u32::from(ms % 1000 >= 500));
+                }
+                if options.zero_length_unknown && metadata.duration == Some(0) {
+                    metadata.duration = None;
+                }
+                // Keep whatever came before the `#EXTINF`, unless it belongs to an earlier
+                // `#EXTINF` that never got a path of its own
+                if seen_extinf {
+                    entry_start = None;
+                }
+                if let Some(earlier) = pending.take().filter(|_| !seen_extinf) {
+                    metadata.group = earlier.group;
+                    metadata.size = earlier.size;
+                    metadata.played_at = earlier.played_at;
+                    metadata.vlc_options_before = earlier.vlc_options_before;
+                    metadata.directives_before = earlier.directives_before;
+                }
+                pending = Some(metadata);
+                seen_extinf = true;
+            } else if let Some(group) = strip_directive(line, "#EXTGRP:") {
+                pending.get_or_insert_default().group = Some(group.trim().to_string());
+            } else if let Some(size) = strip_directive(line, "#EXTBYT:") {
+                let size = size.trim().parse().map_err(|_| PlaylistError::Parse {
+                    line: Some(index + 1),
+                    message: format!("invalid #EXTBYT size `{}`", size.trim()),
+                })?;
+                pending.get_or_insert_default().size = Some(size);
+            } else if let Some(played) = strip_directive(line, PLAYED) {
+                let seconds = played.trim().parse().map_err(|_| PlaylistError::Parse {
+                    line: Some(index + 1),
+                    message: format!("invalid {PLAYED} time `{}`", played.trim()),
+                })?;
+                let played = UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+                pending.get_or_insert_default().played_at = Some(played);
+            } else if let Some(option) = strip_directive(line, "#EXTVLCOPT:") {
+                let metadata = pending.get_or_insert_default();
+                if seen_extinf {
+                    metadata.vlc_options.push(option.to_string());
+                } else {
+                    metadata.vlc_options_before.push(option.to_string());
+                }
+            } else if options.keep_hls_directives && strip_directive(line, "#EXT-X-").is_some()
+            {
+                if entries.is_empty() && pending.is_none() {
+                    info.directives.push(line.to_string());
+                } else if seen_extinf {
+                    pending.get_or_insert_default().directives.push(line.to_string());
+                } else {
+                    pending.get_or_insert_default().directives_before.push(line.to_string());
+                }
+            } else if let Some(header) = strip_directive(line, "#EXTM3U") {
+                parse_attributes(header, &mut info.attributes);
+            } else if strip_directive(line, END_MARKER).is_some_and(str::is_empty) {
+                info.end_marker = true;
+            } else if let Some(unit) = strip_directive(line, "#EXT-ABS-UNIT:") {
+                milliseconds = unit.trim().eq_ignore_ascii_case("ms");
+                info.millisecond_durations |= milliseconds;
+            } else if let Some(title) = strip_directive(line, "#PLAYLIST:") {
+                info.title = Some(title.trim().to_string());
+            } else if let Some(comment) = line.strip_prefix('#')
+                && options.comment_titles
+                && strip_directive(line, "#EXT").is_none()
+            {
+                // A block of several comments is just that, not a title
+                comment_title = (!after_comment).then(|| (comment.trim(), offset(line)));
+                previous_comment = true;
+            } else if line.is_empty() || line.starts_with('#') {
+                // Blank lines, the header, comments, and any other directives
+            } else if options.recover_xml_refs && line.starts_with('<') {
+                // Stray markup, which only counts if it references something
+                if let Some(href) = xml_ref(line) {
+                    seen_extinf = false;
+                    let path = match href {
+                        Cow::Borrowed(href) => entry_path(href, options),
+                        Cow::Owned(href) => {
+                            Cow::Owned(entry_path(&href, options).into_owned())
+                        }
+                    };
+                    let span = span(entry_start.take(), line);
+                    entries.push(M3uEntry::new(
+                        base.num(entries.len()),
+                        path,
+                        pending.take(),
+                        span,
+                    ));
+                }
+            } else if options.split_separated_lines {
+                seen_extinf = false;
+                for part in line.split([',', ';']).map(str::trim).filter(|p| !p.is_empty()) {
+                    let (path, span) =
+                        (entry_path(part, options), span(entry_start.take(), part));
+                    entries.push(M3uEntry::new(
+                        base.num(entries.len()),
+                        path,
+                        pending.take(),
+                        span,
+                    ));
+                }
+            } else {
+                seen_extinf = false;
+                let (path, span) = (entry_path(line, options), span(entry_start.take(), line));
+                entries.push(M3uEntry::new(
+                    base.num(entries.len()),
+                    path,
+                    pending.take(),
+                    span,
+                ));
+            }
+            if pending.is_some() {
+                entry_start.get_or_insert(offset(line));
+            }
+        }
+        // Directives after the last entry have no entry to go with
+        if let Some(leftover) = pending {
+            info.trailing_directives.extend(leftover.directives_before);
+            info.trailing_directives.extend(leftover.directives);
+        }
+        Ok(Self::from_parts(info, entries))
+    }
+
+    /// Copy all borrowed filenames, so the playlist no longer depends on the parsed text.
+    pub fn into_owned(self) -> M3u<'static> {
+        let (info, entries) = self.into_parts();
+        M3u::from_parts(info, entries.iter().map(M3uEntry::clone_owned).collect())
+    }
+}
+
+impl M3u<'static> {
+    /// Read an M3U or EXT-M3U playlist found at `fname` from `reader`, like
+    /// [`parse`](M3u::parse) does for text that's already in memory.
+    ///
+    /// [`ParseOptions::max_line_len`] is enforced while reading, so an overly long line is
+    /// rejected before it's read into memory in full.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Io`] if reading fails or the text isn't valid UTF-8, and the
+    /// same errors [`parse`](M3u::parse) does otherwise.
+    pub fn from_reader(
+        fname: impl Into<String>,
+        reader: impl io::BufRead,
+        options: &ParseOptions,
+    ) -> Result<Self, PlaylistError> {
+        let text = read_limited(reader, options.max_line_len)?;
+        Ok(M3u::parse(fname, &text, options)?.into_owned())
+    }
+
+    /// Read every playlist in `dir` and [`concat`](Playlist::concat) them into one.
+    ///
+    /// Files are read in order of their names, with their format detected the same way
+    /// [`open`](crate::open()) does. Anything that isn't a recognized playlist is skipped.
+    /// With `recursive`, subdirectories are read as well, in that same order. Symlinked
+    /// subdirectories are skipped, so a link pointing back up can't loop forever. Relative
+    /// entries are made absolute against the directory of the playlist they came from,
+    /// since the combined playlist doesn't live next to all of them. An empty directory
+    /// gives an empty playlist named after it.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Io`] if a directory or playlist can't be read, and passes
+    /// on any errors from parsing the playlists that are found.
+    pub fn from_directory_concat(dir: &Path, recursive: bool) -> Result<Self, PlaylistError> {
+        let mut lists = Vec::new();
+        Self::read_directory(&FormatRegistry::builtin(), dir, recursive, &mut lists)?;
+        let lists: Vec<&Self> = lists.iter().collect();
+        Ok(Self::concat(&lists).unwrap_or_else(|| {
+            let fname = dir.to_string_lossy().into_owned();
+            Self::from_parts(M3uInfo { fname, ..Default::default() }, Vec::new())
+        }))
+    }
+
+    /// Recursive part of [`from_directory_concat`](Self::from_directory_concat), adding
+    /// the playlists found in `dir` to `lists`.
+    fn read_directory(
+        registry: &FormatRegistry<Self>,
+        dir: &Path,
+        recursive: bool,
+        lists: &mut Vec<Self>,
+    ) -> Result<(), PlaylistError> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        let base = std::path::absolute(dir)?;
+        for path in paths {
+            if path.is_dir() {
+                // A symlinked directory could point back up and have this recurse forever
+                if recursive && !path.symlink_metadata()?.is_symlink() {
+                    Self::read_directory(registry, &path, recursive, lists)?;
+                }
+                continue;
+            }
+            // Sniffing the start is enough, no need to read every media file in full
+            let fname = path.to_string_lossy();
+            let mut head = Vec::new();
+            std::fs::File::open(&path)?.take(1024).read_to_end(&mut head)?;
+            // Leave out a line that got cut off, it might not look like one anymore
+            if head.len() == 1024
+                && let Some(end) = head.iter().rposition(|&b| b == b'\n')
+            {
+                head.truncate(end + 1);
+            }
+            if registry.detect(&fname, &String::from_utf8_lossy(&head)).is_none() {
+                continue;
+            }
+            let file = io::BufReader::new(std::fs::File::open(&path)?);
+            let list = registry
+                .detect_and_load(&fname, &read_limited(file, DEFAULT_MAX_LINE_LEN)?)?;
+            list.rewrite_filenames(|fname| {
+                let path = local_path(fname).filter(|path| path.is_relative())?;
+                Some(normalize_path(base.join(path)).to_string_lossy().into_owned())
+            });
+            lists.push(list);
+        }
+        Ok(())
+    }
+}
+
+impl M3u<'_> {
+    /// Write the playlist as EXT-M3U to the file at `path`, replacing it if it exists.
+    ///
+    /// The file is written through a [`BufWriter`](io::BufWriter) one entry at a time, so
+    /// huge playlists never have to exist as one big string in memory. With
+    /// [`SaveOptions::index_sidecar`], the entry offsets are written to a `.idx` file next
+    /// to it afterwards. See [`write_to`](Self::write_to) for the details and errors.
+    pub fn save_to(
+        &self,
+        path: impl AsRef<Path>,
+        options: &SaveOptions,
+    ) -> Result<(), PlaylistError> {
+        let path = path.as_ref();
+        let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+        let mut offsets = Vec::new();
+        self.write_entries(&mut out, options, options.index_sidecar.then_some(&mut offsets))?;
+        out.flush()?;
+        if options.index_sidecar {
+            let mut index = path.as_os_str().to_owned();
+            index.push(".idx");
+            let mut index = io::BufWriter::new(std::fs::File::create(index)?);
+            for offset in offsets {
+                writeln!(index, "{offset}")?;
+            }
+            index.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the playlist as EXT-M3U.
+    ///
+    /// The [`shebang`](M3uInfo::shebang) comes first if there is one, then the `#EXTM3U`
+    /// header along with its attributes, followed by a
+    /// `#PLAYLIST` line if the playlist has a title, an [`UNIT_MS`] line if it uses
+    /// [`millisecond_durations`](M3uInfo::millisecond_durations), any kept `#EXT-X-*`
+    /// [`directives`](M3uInfo::directives), and then each entry in turn.
+    ///
+    /// Entries are written to `out` one at a time as they're formatted, nothing is
+    /// collected up front.
+    ///
+    /// The output only depends on the playlist and `options`: attributes are written in
+    /// the order they are stored in, and every line ends in a plain `\n`. Writing the same
+    /// playlist twice therefore always produces the same bytes. With
+    /// [`SaveOptions::pretty`], entries are spaced out and their titles lined up, which
+    /// reads back as the exact same playlist.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::UnrepresentableEntry`] for filenames containing a line
+    /// break, unless [`SaveOptions::encode_newlines`] allows encoding it, and for entries
+    /// with a line break in any of their metadata. A line break in the playlist title,
+    /// header attributes or directives is an [`InvalidInput`](io::ErrorKind::InvalidInput)
+    /// [`PlaylistError::Io`]. All of this is checked before anything is written. Returns
+    /// [`PlaylistError::Io`] if writing to `out` fails as well.
+    pub fn write_to(
+        &self,
+        out: &mut impl Write,
+        options: &SaveOptions,
+    ) -> Result<(), PlaylistError> {
+        self.write_entries(out, options, None)
+    }
+
+    /// Write the playlist like [`write_to`](Self::write_to) does, recording the byte
+    /// offset each entry starts at in `offsets` if given.
+    fn write_entries(
+        &self,
+        out: &mut impl Write,
+        options: &SaveOptions,
+        mut offsets: Option<&mut Vec<u64>>,
+    ) -> Result<(), PlaylistError> {
+        let out = &mut Counting { inner: out, written: 0 };
+        let entries = self.entries.borrow();
+        let needs_encoding = |entry: &M3uEntry| entry.fname.contains(['\n', '\r']);
+        if let Some(entry) = entries.iter().find(|entry| {
+            (needs_encoding(entry) && !(options.encode_newlines && is_file_uri(&entry.fname)))
+                || entry.metadata.borrow().as_ref().is_some_and(M3uMetadata::has_line_breaks)
+        }) {
+            return Err(PlaylistError::UnrepresentableEntry {
+                entry: entry.num,
+                filename: entry.fname.to_string(),
+            });
+        }
+        let info = self.info.borrow();
+        let breaks = |text: &str| text.contains(['\n', '\r']);
+        if info.title.as_deref().is_some_and(breaks)
+            || info.shebang.as_deref().is_some_and(breaks)
+            || info.attributes.iter().any(|(key, value)| breaks(key) || breaks(value))
+            || info.directives.iter().chain(&info.trailing_directives).any(|d| breaks(d))
+        {
+            return Err(PlaylistError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the playlist header contains a line break",
+            )));
+        }
+        if let Some(shebang) = &info.shebang {
+            writeln!(out, "{shebang}")?;
+        }
+        writeln!(out, "#EXTM3U{}", Attributes(&info.attributes))?;
+        if let Some(title) = &info.title {
+            writeln!(out, "#PLAYLIST:{title}")?;
+        }
+        if info.millisecond_durations {
+            writeln!(out, "{UNIT_MS}")?;
+        }
+        for directive in &info.directives {
+            writeln!(out, "{directive}")?;
+        }
+        let metadata = |entry: &M3uEntry| {
+            entry.metadata().map(|mut metadata| {
+                if info.millisecond_durations {
+                    metadata.duration = metadata.duration.map(|s| s.saturating_mul(1000));
+                }
+                metadata
+            })
+        };
+        // Only pretty output lines up titles, and only over the lines that have one
+        let width = if options.pretty {
+            entries
+                .iter()
+                .filter_map(|e| metadata(e).filter(M3uMetadata::writes_extinf))
+                .map(|m| m.extinf_prefix().chars().count())
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        for (index, entry) in entries.iter().enumerate() {
+            if options.pretty && index > 0 {
+                writeln!(out)?;
+            }
+            if let Some(offsets) = offsets.as_deref_mut() {
+                offsets.push(out.written);
+            }
+            let metadata = match metadata(entry) {
+                Some(metadata) if options.pretty => format!("{metadata:width$}"),
+                Some(metadata) => metadata.to_string(),
+                None => String::new(),
+            };
+            if !metadata.is_empty() {
+                writeln!(out, "{metadata}")?;
+            }
+            if needs_encoding(entry) {
+                writeln!(out, "{}", entry.fname.replace('\r', "%0D").replace('\n', "%0A"))?;
+            } else {
+                writeln!(out, "{}", entry.fname)?;
+            }
+        }
+        for directive in &info.trailing_directives {
+            writeln!(out, "{directive}")?;
+        }
+        if options.end_marker {
+            writeln!(out, "{END_MARKER}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A writer that keeps track of how many bytes went through it.
+struct Counting<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl PlaylistOps for M3u<'_> {
+    fn title(&self) -> Option<String> {
+        self.info.borrow().title.clone()
+    }
+
+    fn filename(&self) -> String {
+        self.info.borrow().fname.clone()
+    }
+
+    fn count(&self) -> usize {
+        Playlist::count(self)
+    }
+
+    fn entry_filename(&self, index: usize) -> Option<String> {
+        self.entries.borrow().get(index).map(|e| e.fname.to_string())
+    }
+
+    fn entry_display_name(&self, index: usize) -> Option<String> {
+        self.entries.borrow().get(index).map(Entry::display_name)
+    }
+
+    fn add_path(&self, fname: &str) {
+        let num = self.info.borrow().numbering_base.num(Playlist::count(self));
+        self.add_entry(M3uEntry::new(num, Cow::Owned(fname.to_string()), None, None));
+    }
+
+    fn remove_at(&self, index: usize) -> bool {
+        let mut entries = self.entries.borrow_mut();
+        (index < entries.len()).then(|| entries.remove(index)).is_some()
+    }
+
+    fn dedup(&self, strategy: DedupStrategy) -> usize {
+        self.dedup_by(strategy)
+    }
+
+    fn write_to(
+        &self,
+        mut out: &mut dyn io::Write,
+        options: &SaveOptions,
+    ) -> Result<(), PlaylistError> {
+        M3u::write_to(self, &mut out, options)
+    }
+
+    fn save_to(&self, path: &Path, options: &SaveOptions) -> Result<(), PlaylistError> {
+        M3u::save_to(self, path, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    fn filenames(list: &M3u) -> Vec<String> {
+        list.entries.borrow().iter().map(|e| e.fname.to_string()).collect()
+    }
+
+    fn save(list: &M3u, options: &SaveOptions) -> String {
+        let mut out = Vec::new();
+        list.write_to(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn entries_are_read_with_their_metadata() {
+        let text = concat!(
+            "\u{feff}#EXTM3U\n#PLAYLIST:Mix\n",
+            "#EXTGRP:Rock\n#EXTINF:212 tvg-id=\"a\" tvg-logo=x.png,Song, Part 2\na.mp3\n",
+            "\n# Just a comment\nb.mp3\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.info.borrow().title.as_deref(), Some("Mix"));
+        let entries = list.entries.borrow();
+        let read: Vec<_> = entries.iter().map(|e| (e.num, e.fname.to_string())).collect();
+        assert_eq!(read, [(1, "a.mp3".to_string()), (2, "b.mp3".to_string())]);
+        let metadata = entries[0].metadata().unwrap();
+        assert_eq!((metadata.duration, metadata.title.as_str()), (Some(212), "Song, Part 2"));
+        assert_eq!(metadata.group.as_deref(), Some("Rock"));
+        assert_eq!(
+            (metadata.attribute("tvg-id"), metadata.attribute("tvg-logo")),
+            (Some("a"), Some("x.png"))
+        );
+        assert!(entries[1].metadata().is_none());
+
+        let err =
+            M3u::parse("x.m3u", "a.mp3\n#EXTINF:soon,A\nb.mp3\n", &ParseOptions::default());
+        assert!(matches!(err.err().unwrap(), PlaylistError::Parse { line: Some(2), .. }));
+    }
+
+    #[test]
+    fn malformed_entries_are_errors_not_panics() {
+        let err = parse_extinf("ten,Title").err().unwrap();
+        assert!(matches!(&err, PlaylistError::Parse { line: None, message }
+            if message.contains("`ten`")));
+        // Whole playlists report the line the bad entry is on
+        let text = "#EXTM3U\na.mp3\n#EXTINF:ten,Title\nb.mp3\n";
+        let err = M3u::parse("x.m3u", text, &ParseOptions::default()).err().unwrap();
+        assert!(matches!(err, PlaylistError::Parse { line: Some(3), .. }));
+        assert!(err.to_string().contains('3'));
+    }
+
+    #[test]
+    fn builder_sets_every_field() {
+        let entry = M3uEntryBuilder::new("http://tv/news")
+            .num(4)
+            .title("News 24")
+            .duration(90)
+            .group("News")
+            .attribute("tvg-id", "news.uk")
+            .attribute("tvg-logo", "http://tv/logo.png")
+            .build();
+        assert_eq!(entry.num, 4);
+        assert_eq!(
+            entry.to_string(),
+            concat!(
+                "#EXTINF:90 tvg-id=\"news.uk\" tvg-logo=\"http://tv/logo.png\",News 24\n",
+                "#EXTGRP:News\n",
+                "http://tv/news",
+            )
+        );
+        let bare = M3uEntryBuilder::new("a.mp3").build();
+        assert_eq!((bare.to_string(), bare.metadata()), ("a.mp3".to_string(), None));
+    }
+
+    #[test]
+    fn hls_playlists_are_classified_by_kind() {
+        let master = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1280x720\n",
+            "720p/index.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=640000\n",
+            "360p/index.m3u8\n",
+        );
+        let media = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:10\n",
+            "#EXTINF:9.009,\n",
+            "segment0.ts\n",
+            "#EXT-X-ENDLIST\n",
+        );
+        assert_eq!(hls_kind(master), Some(HlsKind::Master));
+        assert_eq!(hls_kind(media), Some(HlsKind::Media));
+        assert_eq!(hls_kind("#EXTM3U\n#EXTINF:3,A\na.mp3\n"), None);
+    }
+
+    #[test]
+    fn separated_lines_are_split_under_the_flag() {
+        let text = "#EXTM3U\n#EXTINF:3,A\na.mp3, b.mp3;c.mp3;;\nd, e.mp3\n";
+        let options = ParseOptions { split_separated_lines: true, ..Default::default() };
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        assert_eq!(filenames(&list), ["a.mp3", "b.mp3", "c.mp3", "d", "e.mp3"]);
+        // The metadata goes with the first path on the line
+        let metadata: Vec<_> = list.entries.borrow().iter().map(|e| e.metadata()).collect();
+        assert_eq!(metadata[0].as_ref().unwrap().title, "A");
+        assert!(metadata[1..].iter().all(Option::is_none));
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(filenames(&list), ["a.mp3, b.mp3;c.mp3;;", "d, e.mp3"]);
+    }
+
+    #[test]
+    fn tvg_shift_is_read_at_both_levels() {
+        let text = concat!(
+            "#EXTM3U tvg-shift=\"-2\" x-tvg-url=\"http://epg/guide.xml\"\n",
+            "#EXTINF:-1 tvg-id=\"bbc1\" tvg-shift=\"-1.5\",BBC One\n",
+            "http://tv/bbc1\n",
+            "#EXTINF:-1 tvg-shift=\"+3\",NHK\n",
+            "http://tv/nhk\n",
+            "#EXTINF:-1 tvg-shift=-4,CNN\n",
+            "http://tv/cnn\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.info.borrow().tvg_shift(), Some(-2));
+        let shifts: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.metadata().unwrap().tvg_shift()).collect();
+        // Fractional hours aren't whole hours, the attribute is still kept as-is
+        assert_eq!(shifts, [None, Some(3), Some(-4)]);
+        let saved = save(&list, &SaveOptions::default());
+        assert_eq!(saved, text.replace("tvg-shift=-4", "tvg-shift=\"-4\""));
+        let reread = M3u::parse("x.m3u", &saved, &ParseOptions::default()).unwrap();
+        assert_eq!(reread.info.borrow().tvg_shift(), Some(-2));
+        assert_eq!(
+            reread.entries.borrow()[0].metadata().unwrap().attribute("tvg-shift"),
+            Some("-1.5")
+        );
+    }
+
+    #[test]
+    fn saving_is_deterministic() {
+        let text = concat!(
+            "#EXTM3U x-tvg-url=\"http://epg/a.xml\" tvg-shift=\"1\" url-tvg=\"http://epg/b\"\r\n",
+            "#PLAYLIST:Mix\r\n",
+            "#EXTINF:-1 tvg-name=\"B\" tvg-id=\"b\" group-title=\"News\" tvg-logo=\"l\",B\r\n",
+            "http://tv/b\r\n",
+            "#EXTINF:3 z=\"1\" a=\"2\" m=\"3\",A\r\n#EXTGRP:G\r\n#EXTBYT:10\r\na.mp3\r\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let options = SaveOptions { end_marker: true, ..Default::default() };
+        let saved = save(&list, &options);
+        assert_eq!(saved, save(&list, &options));
+        assert!(!saved.contains('\r'));
+        assert!(saved.contains("z=\"1\" a=\"2\" m=\"3\""));
+        // A separate load of the same file saves to the same bytes as well
+        let reloaded = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(save(&reloaded, &options), saved);
+    }
+
+    #[test]
+    fn duration_kinds_tell_live_from_unknown() {
+        let text =
+            "#EXTINF:212,Known\na.mp3\n#EXTINF:-1,Live\nhttp://radio/s\n#EXTGRP:G\nb.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let kinds: Vec<_> = list
+            .entries
+            .borrow()
+            .iter()
+            .map(|e| e.metadata().unwrap().duration_kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            [DurationKind::Known(212), DurationKind::Live, DurationKind::Unknown]
+        );
+        let lengths: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.metadata().unwrap().len()).collect();
+        assert_eq!(lengths, [Some(212), None, None]);
+        assert_eq!(M3uMetadata::default().duration_kind(), DurationKind::Unknown);
+    }
+
+    #[test]
+    fn millisecond_durations_convert_both_ways() {
+        let text = concat!(
+            "#EXTM3U\n#EXTINF:212,Seconds\na.mp3\n",
+            "#EXT-ABS-UNIT:ms\n",
+            "#EXTINF:212500,Rounded up\nb.mp3\n#EXTINF:1499,Rounded down\nc.mp3\n",
+            "#EXTINF:-1,Live\nhttp://radio/s\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert!(list.info.borrow().millisecond_durations);
+        let lengths: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.metadata().unwrap().len()).collect();
+        assert_eq!(lengths, [Some(212), Some(213), Some(1), None]);
+        let saved = save(&list, &SaveOptions::default());
+        assert_eq!(
+            saved,
+            concat!(
+                "#EXTM3U\n#EXT-ABS-UNIT:ms\n",
+                "#EXTINF:212000,Seconds\na.mp3\n",
+                "#EXTINF:213000,Rounded up\nb.mp3\n#EXTINF:1000,Rounded down\nc.mp3\n",
+                "#EXTINF:-1,Live\nhttp://radio/s\n",
+            )
+        );
+        let reread = M3u::parse("x.m3u", &saved, &ParseOptions::default()).unwrap();
+        assert_eq!(*reread.entries.borrow(), *list.entries.borrow());
+    }
+
+    #[test]
+    fn mixed_case_directives_are_recognized() {
+        let text = concat!(
+            "#ExtM3U tvg-shift=\"2\"\n",
+            "#Extinf:3,Mixed Case\na.mp3\n",
+            "#extgrp:RoCk\n#eXtInF:-1,Radio\nhttp://radio/s\n",
+            "#PlayList:Mix\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.info.borrow().tvg_shift(), Some(2));
+        let entries = list.entries.borrow();
+        let meta: Vec<_> = entries.iter().map(|e| e.metadata().unwrap()).collect();
+        assert_eq!((meta[0].title.as_str(), meta[0].duration), ("Mixed Case", Some(3)));
+        assert_eq!(meta[1].duration_kind(), DurationKind::Live);
+        assert_eq!(meta[1].group.as_deref(), Some("RoCk"));
+        assert_eq!(list.info.borrow().title.as_deref(), Some("Mix"));
+        assert_eq!(filenames(&list), ["a.mp3", "http://radio/s"]);
+    }
+
+    #[test]
+    fn extinf_is_written_by_duration_kind() {
+        let written = |metadata: M3uMetadata| {
+            M3uEntry::new(0, "a.mp3".into(), Some(metadata), None).to_string()
+        };
+        let titled =
+            |title: &str| M3uMetadata { title: title.to_string(), ..Default::default() };
+        let live = M3uMetadata { live: true, ..titled("Radio") };
+        assert_eq!(written(live), "#EXTINF:-1,Radio\na.mp3");
+        let known = M3uMetadata { duration: Some(212), ..titled("Song") };
+        assert_eq!(written(known), "#EXTINF:212,Song\na.mp3");
+        assert_eq!(written(titled("Unknown")), "#EXTINF:-1,Unknown\na.mp3");
+        let grouped = M3uMetadata { group: Some("G".to_string()), ..Default::default() };
+        assert_eq!(written(grouped), "#EXTGRP:G\na.mp3");
+        let text = "#EXTINF:-1,Radio\nhttp://radio/s\n#EXTINF:212,Song\na.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(save(&list, &SaveOptions::default()), format!("#EXTM3U\n{text}"));
+    }
+
+    #[test]
+    fn bare_and_extended_entries_mix() {
+        let text = concat!(
+            "first.mp3\n",
+            "#EXTINF:3,Second\nsecond.mp3\n",
+            "third.mp3\n",
+            "#EXTINF:9,Dangling\n#EXTINF:4,Fourth\nfourth.mp3\n",
+            "fifth.mp3\n",
+            "#EXTINF:5,Trailing\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            filenames(&list),
+            ["first.mp3", "second.mp3", "third.mp3", "fourth.mp3", "fifth.mp3"]
+        );
+        let titles: Vec<_> = list
+            .entries
+            .borrow()
+            .iter()
+            .map(|e| e.metadata().map(|m| (m.title.clone(), m.duration)))
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                None,
+                Some(("Second".to_string(), Some(3))),
+                None,
+                Some(("Fourth".to_string(), Some(4))),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_after_a_bom_report_the_file_line() {
+        let text =
+            "\u{feff}#EXTM3U\r\n#EXTINF:3,A\r\na.mp3\r\n\r\n#EXTINF:soon,B\r\nb.mp3\r\n";
+        let line_of = |result: Result<M3u, PlaylistError>| match result.err().unwrap() {
+            PlaylistError::Parse { line, .. } => line,
+            err => panic!("{err:?}"),
+        };
+        assert_eq!(line_of(M3u::parse("x.m3u", text, &ParseOptions::default())), Some(5));
+        let reader = io::Cursor::new(text.as_bytes());
+        assert_eq!(line_of(M3u::from_reader("x.m3u", reader, &Default::default())), Some(5));
+    }
+
+    #[test]
+    fn entries_are_written_one_at_a_time() {
+        /// Keeps every chunk it's handed separately.
+        struct Recording(Vec<Vec<u8>>);
+        impl Write for Recording {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(buf.to_vec());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let text: String =
+            (0..1000).map(|i| format!("#EXTINF:{i},Track {i}\ntrack{i}.mp3\n")).collect();
+        let list = M3u::parse("x.m3u", &text, &ParseOptions::default()).unwrap();
+        let mut out = Recording(Vec::new());
+        list.write_to(&mut out, &SaveOptions::default()).unwrap();
+        assert!(out.0.len() >= 1000);
+        assert!(out.0.iter().all(|chunk| chunk.len() < 64), "a chunk holds several entries");
+        assert_eq!(out.0.concat(), save(&list, &SaveOptions::default()).into_bytes());
+    }
+
+    #[test]
+    fn shebang_lines_round_trip() {
+        let text = "#!/usr/bin/env mpv\n#EXTM3U\n#EXTINF:3,A\na.mp3\nb.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.info.borrow().shebang.as_deref(), Some("#!/usr/bin/env mpv"));
+        assert_eq!(filenames(&list), ["a.mp3", "b.mp3"]);
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        // Only the very first line can be one
+        let late =
+            M3u::parse("x.m3u", "#EXTM3U\n#!/usr/bin/env mpv\na.mp3\n", &Default::default());
+        assert_eq!(late.unwrap().info.borrow().shebang, None);
+    }
+
+    #[test]
+    fn index_offsets_point_at_each_entry() {
+        let dir = temp_dir("index-offsets");
+        let text = concat!(
+            "#EXTM3U\n#PLAYLIST:Mix\n",
+            "#EXTINF:3,A\na.mp3\n",
+            "b.mp3\n",
+            "#EXTGRP:G\n#EXTINF:-1,Radio\nhttp://radio/s\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        for pretty in [false, true] {
+            let path = dir.join("list.m3u");
+            let options = SaveOptions { pretty, index_sidecar: true, ..Default::default() };
+            list.save_to(&path, &options).unwrap();
+            let saved = std::fs::read_to_string(&path).unwrap();
+            let offsets: Vec<usize> = std::fs::read_to_string(dir.join("list.m3u.idx"))
+                .unwrap()
+                .lines()
+                .map(|line| line.parse().unwrap())
+                .collect();
+            // Pretty output pads the titles, so only look up to the first space or comma
+            let starts: Vec<_> =
+                offsets.iter().map(|&o| saved[o..].split([' ', ',', '\n']).next()).collect();
+            assert_eq!(starts, [Some("#EXTINF:3"), Some("b.mp3"), Some("#EXTINF:-1")]);
+            assert_eq!(offsets.len(), list.entries.borrow().len(), "pretty: {pretty}");
+        }
+    }
+
+    #[test]
+    fn from_directory_concat_reads_every_playlist() {
+        let dir = temp_dir("concat");
+        std::fs::write(
+            dir.join("a.m3u"),
+            "#PLAYLIST:Mix\n#EXTINF:3,A\na.mp3\nhttp://radio/x\n",
+        )
+        .unwrap();
+        // There's no PLS reader yet, so this gets skipped along with the media file
+        std::fs::write(dir.join("b.pls"), "[playlist]\nFile1=b.mp3\nNumberOfEntries=1\n")
+            .unwrap();
+        std::fs::write(dir.join("c.mp3"), [0xff, 0xfb, 0x90, 0x00]).unwrap();
+        std::fs::write(dir.join("e.txt"), "e.mp3\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "Remember to buy milk\nCall mom\n").unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/d.m3u8"), "#EXTM3U\nd.mp3\n").unwrap();
+        let dir = std::path::absolute(dir).unwrap();
+        let at = |path: &str| dir.join(path).to_string_lossy().into_owned();
+
+        let flat = M3u::from_directory_concat(&dir, false).unwrap();
+        assert_eq!(flat.info.borrow().title.as_deref(), Some("Mix"));
+        assert_eq!(filenames(&flat), [at("a.mp3"), "http://radio/x".to_string(), at("e.mp3")]);
+        let deep = M3u::from_directory_concat(&dir, true).unwrap();
+        assert_eq!(
+            filenames(&deep),
+            [at("a.mp3"), "http://radio/x".to_string(), at("e.mp3"), at("sub/d.mp3")]
+        );
+
+        // Long enough that sniffing the start cuts a line in half
+        let long = temp_dir("concat-long");
+        let name = "Long Track Name ".repeat(8);
+        let tracks: String = (0..10).map(|i| format!("{name}{i}.mp3\n")).collect();
+        std::fs::write(long.join("long.txt"), tracks).unwrap();
+        assert_eq!(Playlist::count(&M3u::from_directory_concat(&long, false).unwrap()), 10);
+
+        let empty = temp_dir("concat-empty");
+        assert_eq!(Playlist::count(&M3u::from_directory_concat(&empty, true).unwrap()), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_directory_concat_skips_symlinked_directories() {
+        let dir = std::path::absolute(temp_dir("concat-symlink")).unwrap();
+        std::fs::write(dir.join("a.m3u"), "#EXTM3U\na.mp3\n").unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/b.m3u"), "#EXTM3U\nb.mp3\n").unwrap();
+        std::os::unix::fs::symlink(".", dir.join("self")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub/up")).unwrap();
+        let deep = M3u::from_directory_concat(&dir, true).unwrap();
+        let at = |path: &str| dir.join(path).to_string_lossy().into_owned();
+        assert_eq!(filenames(&deep), [at("a.mp3"), at("sub/b.mp3")]);
+    }
+
+    #[test]
+    fn durations_accept_clock_formats() {
+        assert_eq!(parse_duration("03:32").unwrap(), Some(212));
+        assert_eq!(parse_duration("1:02:03").unwrap(), Some(3723));
+        assert_eq!(parse_duration("212").unwrap(), Some(212));
+        assert_eq!(parse_duration("-1").unwrap(), None);
+        assert!(parse_duration("1:60").is_err());
+        assert!(parse_duration("1:2:3:4").is_err());
+    }
+
+    #[test]
+    fn fractional_durations_round_to_the_nearest_second() {
+        assert_eq!(parse_duration("212.5").unwrap(), Some(213));
+        assert_eq!(parse_duration("9.009").unwrap(), Some(9));
+        assert_eq!(parse_duration("3:31.75").unwrap(), Some(212));
+        assert!(parse_duration("9.").is_err());
+        assert!(parse_duration("9.0.1").is_err());
+        let list = M3u::parse("x.m3u8", "#EXTINF:9.009,\nseg0.ts\n", &ParseOptions::default());
+        assert_eq!(list.unwrap().entries.borrow()[0].metadata().unwrap().duration, Some(9));
+    }
+
+    #[test]
+    fn entries_know_their_source_span() {
+        let text = concat!(
+            "\u{feff}#EXTM3U\r\n#EXTINF:3,A\r\na.mp3\r\n\r\n",
+            "#EXTGRP:g\r\n#EXTINF:4,B\r\nb.mp3\r\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let spans: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.source_span().unwrap()).collect();
+        assert_eq!(&text[spans[0].clone()], "#EXTINF:3,A\r\na.mp3");
+        assert_eq!(&text[spans[1].clone()], "#EXTGRP:g\r\n#EXTINF:4,B\r\nb.mp3");
+        let built = M3uEntryBuilder::new("c.mp3").build();
+        assert_eq!(built.source_span(), None);
+    }
+
+    #[test]
+    fn end_marker_is_only_written_when_enabled() {
+        let list = M3u::parse("x.m3u", "a.mp3\nb.mp3\n", &ParseOptions::default()).unwrap();
+        for enabled in [false, true] {
+            let text = save(&list, &SaveOptions { end_marker: enabled, ..Default::default() });
+            assert_eq!(text.ends_with("#EXT-ABS-ENDLIST\n"), enabled);
+            let reread = M3u::parse("x.m3u", &text, &ParseOptions::default()).unwrap();
+            assert_eq!(reread.info.borrow().end_marker, enabled);
+            assert_eq!(Playlist::count(&reread), 2);
+        }
+        let truncated = "#EXTM3U\na.mp3\nb.mp";
+        let list = M3u::parse("x.m3u", truncated, &ParseOptions::default()).unwrap();
+        assert!(!list.info.borrow().end_marker);
+    }
+
+    #[test]
+    fn setting_a_length_ends_a_live_stream() {
+        let mut metadata = parse_extinf("-1,Radio").unwrap();
+        assert!(metadata.is_live());
+        metadata.set_len(Some(5));
+        assert!(!metadata.live);
+        assert_eq!(metadata.duration_kind(), DurationKind::Known(5));
+    }
+
+    #[test]
+    fn xml_refs_are_recovered_under_the_flag() {
+        let text = concat!(
+            "#EXTM3U\n",
+            "<ref href=\"http://radio/live?id=1&amp;fmt=mp3\" />\n",
+            "<entry><REF SRC='http://radio/b'></entry>\n",
+            "<title>No reference</title>\n",
+        );
+        let options = ParseOptions { recover_xml_refs: true, ..Default::default() };
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        assert_eq!(filenames(&list), ["http://radio/live?id=1&fmt=mp3", "http://radio/b"]);
+        assert_eq!(xml_ref("<ref href=\"a&amp;lt;b&quot;\">").unwrap(), "a&lt;b\"");
+        // Without the flag the tags are taken as paths
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(Playlist::count(&list), 3);
+    }
+
+    #[test]
+    fn entries_are_numbered_from_the_configured_base() {
+        let dir = temp_dir("numbering");
+        std::fs::create_dir(dir.join("album")).unwrap();
+        std::fs::write(dir.join("album/1.mp3"), "").unwrap();
+        std::fs::write(dir.join("album/2.mp3"), "").unwrap();
+        let nums =
+            |list: &M3u| list.entries.borrow().iter().map(|e| e.num).collect::<Vec<_>>();
+        for (base, first) in [(NumberingBase::Zero, 0), (NumberingBase::One, 1)] {
+            let options = ParseOptions { numbering_base: base, ..Default::default() };
+            let list = M3u::parse("x.m3u", "a.mp3\nalbum/\n", &options).unwrap();
+            assert_eq!(nums(&list), [first, first + 1]);
+            list.add_path("b.mp3");
+            assert_eq!(nums(&list), [first, first + 1, first + 2]);
+            list.expand_directories(&dir, &["mp3"]).unwrap();
+            assert_eq!(filenames(&list), ["a.mp3", "album/1.mp3", "album/2.mp3", "b.mp3"]);
+            assert_eq!(nums(&list)[..3], [first, first + 1, first + 2]);
+            list.renumber(base);
+            assert_eq!(nums(&list), [first, first + 1, first + 2, first + 3]);
+        }
+    }
+
+    #[test]
+    fn comment_titles_round_trip_as_comments() {
+        let options = ParseOptions { comment_titles: true, ..Default::default() };
+        let text = "# Intro\nintro.mp3\n# Just a note\n# about the next one\nnext.mp3\n";
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        let titles: Vec<_> = list.entries.borrow().iter().map(Entry::display_name).collect();
+        assert_eq!(titles, ["Intro", "next"]);
+        let saved = save(&list, &SaveOptions::default());
+        assert_eq!(saved, "#EXTM3U\n# Intro\nintro.mp3\nnext.mp3\n");
+        let reread = M3u::parse("x.m3u", &saved, &options).unwrap();
+        let first = reread.entries.borrow()[0].clone();
+        assert_eq!(
+            first.metadata.borrow().as_ref().unwrap().duration_kind(),
+            DurationKind::Unknown
+        );
+        assert_eq!(first.display_name(), "Intro");
+        // Once there's a length to write, the title goes on the `#EXTINF` line
+        list.entries.borrow_mut()[0].metadata.borrow_mut().as_mut().unwrap().set_len(Some(5));
+        assert!(
+            save(&list, &SaveOptions::default()).starts_with("#EXTM3U\n#EXTINF:5,Intro\n")
+        );
+    }
+
+    #[test]
+    fn hls_directives_round_trip_unchanged() {
+        let text = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:3\n",
+            "#EXT-X-TARGETDURATION:10\n",
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"https://keys/1\"\n",
+            "#EXTINF:9.009,\n",
+            "#EXT-X-BYTERANGE:75232@0\n",
+            "segment0.ts\n",
+            "#EXT-X-DISCONTINUITY\n",
+            "#EXTINF:10.0,\n",
+            "segment1.ts\n",
+            "#EXT-X-ENDLIST\n",
+        );
+        let options = ParseOptions {
+            keep_hls_directives: true,
+            keep_raw_extinf: true,
+            ..Default::default()
+        };
+        let list = M3u::parse("x.m3u8", text, &options).unwrap();
+        assert_eq!(filenames(&list), ["segment0.ts", "segment1.ts"]);
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        // Without the flag they're dropped like any other unsupported directive
+        let list = M3u::parse("x.m3u8", text, &ParseOptions::default()).unwrap();
+        assert!(!save(&list, &SaveOptions::default()).contains("#EXT-X-"));
+    }
+
+    #[test]
+    fn entries_compare_without_their_number() {
+        let list = M3u::parse(
+            "x.m3u",
+            "a.mp3\n#EXTINF:3,A\na.mp3\na.mp3\n",
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        let entries = list.entries.borrow();
+        assert_eq!(entries[0], entries[2]);
+        assert_ne!(entries[0].num, entries[2].num);
+        assert_ne!(entries[0], entries[1]);
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_occurrence_in_order() {
+        let text = "a.mp3\nb.mp3\na.mp3\nc.mp3\n#EXTINF:3,B\nb.mp3\nb.mp3\nd.mp3\na.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(list.dedup_entries(), 3);
+        let kept: Vec<_> =
+            list.entries.borrow().iter().map(|e| (e.num, e.fname.to_string())).collect();
+        let expected = [(1, "a.mp3"), (2, "b.mp3"), (4, "c.mp3"), (5, "b.mp3"), (7, "d.mp3")];
+        assert_eq!(kept, expected.map(|(num, fname)| (num, fname.to_string())));
+        assert_eq!(list.entries.borrow()[3].metadata().unwrap().title, "B");
+    }
+
+    #[test]
+    fn overlong_lines_are_rejected_while_reading() {
+        let options = ParseOptions { max_line_len: 16, ..Default::default() };
+        let too_long = |err, at| {
+            let PlaylistError::Parse { line: Some(line), .. } = err else { return false };
+            line == at
+        };
+        // An endless line would never finish reading if it was buffered in full
+        let endless = io::BufReader::new(io::repeat(b'a'));
+        assert!(too_long(M3u::from_reader("x.m3u", endless, &options).err().unwrap(), 1));
+        let text = "#EXTM3U\r\nshort.mp3\r\n/a/path/that/is/too/long.mp3\r\n";
+        let err = M3u::from_reader("x.m3u", text.as_bytes(), &options).err().unwrap();
+        assert!(too_long(err, 3));
+        assert!(too_long(M3u::parse("x.m3u", text, &options).err().unwrap(), 3));
+        // Line breaks don't count, so a line of exactly the limit is fine
+        let list = M3u::from_reader("x.m3u", "0123456789abcdef\r\n".as_bytes(), &options);
+        assert_eq!(Playlist::count(&list.unwrap()), 1);
+    }
+
+    #[test]
+    fn open_limits_line_length() {
+        let path = temp_dir("open-limit").join("huge.m3u");
+        let mut text = "#EXTM3U\n".to_string();
+        text.push_str(&"a".repeat(DEFAULT_MAX_LINE_LEN + 1));
+        std::fs::write(&path, text).unwrap();
+        let err = crate::open(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, PlaylistError::Parse { line: Some(2), .. }));
+    }
+
+    #[test]
+    fn raw_extinf_lines_are_kept_byte_for_byte() {
+        let options = ParseOptions { keep_raw_extinf: true, ..Default::default() };
+        let text = "#EXTM3U\n#EXTINF:10,  Title  \na.mp3\n  #EXTINF:0010 , B\tb.mp3\nb.mp3\n";
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        // An edited entry is written from its fields again
+        list.entries.borrow_mut()[0].metadata.borrow_mut().as_mut().unwrap().set_len(Some(11));
+        let saved = save(&list, &SaveOptions::default());
+        assert!(saved.starts_with("#EXTM3U\n#EXTINF:11,Title\na.mp3\n  #EXTINF:0010 , B"));
+    }
+
+    #[test]
+    fn zero_lengths_are_known_unless_flagged() {
+        let text = "#EXTM3U\n#EXTINF:0,Jingle\njingle.mp3\n";
+        let kind = |list: &M3u| list.entries.borrow()[0].metadata().unwrap().duration_kind();
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(kind(&list), DurationKind::Known(0));
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        let options = ParseOptions { zero_length_unknown: true, ..Default::default() };
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        assert_eq!(kind(&list), DurationKind::Unknown);
+        // Saved like any other unknown length, which reads back as live
+        let saved = save(&list, &SaveOptions::default());
+        assert_eq!(saved, "#EXTM3U\n#EXTINF:-1,Jingle\njingle.mp3\n");
+        let reread = M3u::parse("x.m3u", &saved, &options).unwrap();
+        assert_eq!(kind(&reread), DurationKind::Live);
+    }
+
+    #[test]
+    fn vlc_options_keep_their_side_of_extinf() {
+        let text = concat!(
+            "#EXTM3U\n",
+            "#EXTVLCOPT:network-caching=1000\n",
+            "#EXTINF:-1,Radio\n",
+            "#EXTVLCOPT:http-user-agent=Absinthium\n",
+            "#EXTVLCOPT:http-referrer=http://radio/\n",
+            "http://radio/live\n",
+            "#EXTINF:3,A\n",
+            "#EXTVLCOPT:start-time=5\n",
+            "a.mp3\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let radio = list.entries.borrow()[0].metadata().unwrap();
+        assert_eq!(radio.vlc_options_before, ["network-caching=1000"]);
+        assert_eq!(radio.vlc_options.len(), 2);
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        // Other directives don't keep their place among the options
+        let text = "#EXTINF:3,A\n#EXTVLCOPT:start-time=5\n#EXTGRP:G\na.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let saved = save(&list, &SaveOptions::default());
+        assert_eq!(saved, "#EXTM3U\n#EXTINF:3,A\n#EXTGRP:G\n#EXTVLCOPT:start-time=5\na.mp3\n");
+    }
+
+    #[test]
+    fn line_breaks_in_filenames_are_rejected_or_encoded() {
+        let list = M3u::parse("x.m3u", "a.mp3\n", &ParseOptions::default()).unwrap();
+        list.add_path("/music/two\nlines.mp3");
+        let encode = SaveOptions { encode_newlines: true, ..Default::default() };
+        for options in [SaveOptions::default(), encode.clone()] {
+            let mut out = Vec::new();
+            let err = list.write_to(&mut out, &options).err().unwrap();
+            let PlaylistError::UnrepresentableEntry { entry, filename } = err else {
+                panic!("{err}")
+            };
+            assert_eq!((entry, filename.as_str()), (2, "/music/two\nlines.mp3"));
+            // Nothing is written before the check
+            assert!(out.is_empty());
+        }
+        list.remove_at(1);
+        list.add_path("FILE:///music/two\r\nlines.mp3");
+        assert!(list.write_to(&mut Vec::new(), &SaveOptions::default()).is_err());
+        let saved = save(&list, &encode);
+        assert_eq!(saved, "#EXTM3U\na.mp3\nFILE:///music/two%0D%0Alines.mp3\n");
+        let reread = M3u::parse("x.m3u", &saved, &ParseOptions::default()).unwrap();
+        let path = local_path(&reread.entries.borrow()[1].fname).unwrap();
+        assert_eq!(path, Path::new("/music/two\r\nlines.mp3"));
+    }
+
+    #[test]
+    fn line_breaks_in_metadata_are_rejected() {
+        let text = "#EXTINF:3,A\n#EXTGRP:G\na.mp3\n";
+        let edits: [fn(&mut M3uMetadata); 5] = [
+            |m| m.title.push_str("\n#EXTINF:1,B"),
+            |m| m.group = Some("G\r\nb.mp3".into()),
+            |m| m.set_attribute("tvg-name", "two\nlines"),
+            |m| m.vlc_options.push("start-time=5\nb.mp3".into()),
+            |m| m.directives.push("#EXT-X-DISCONTINUITY\nb.mp3".into()),
+        ];
+        for edit in edits {
+            let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+            list.for_each_mut(|e| edit(e.metadata.get_mut().as_mut().unwrap()));
+            let mut out = Vec::new();
+            let err = list.write_to(&mut out, &SaveOptions::default()).err().unwrap();
+            let PlaylistError::UnrepresentableEntry { entry, filename } = err else {
+                panic!("{err}")
+            };
+            assert_eq!((entry, filename.as_str()), (1, "a.mp3"));
+            assert!(out.is_empty());
+        }
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        list.info.borrow_mut().directives.push("#EXT-X-VERSION:3\na.mp3".into());
+        let mut out = Vec::new();
+        let err = list.write_to(&mut out, &SaveOptions::default()).err().unwrap();
+        assert!(
+            matches!(err, PlaylistError::Io(e) if e.kind() == io::ErrorKind::InvalidInput)
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn quoted_paths_are_unquoted_under_the_flag() {
+        let text = concat!(
+            "\"C:\\My Music\\a.mp3\"\n",
+            "\"/music/The \\\"Best\\\" Of.mp3\"\n",
+            "\"C:\\My Music\\\"\n",
+            "\"half quoted.mp3\n",
+            "plain.mp3\n",
+        );
+        let options = ParseOptions { unquote_paths: true, ..Default::default() };
+        let list = M3u::parse("x.m3u", text, &options).unwrap();
+        assert_eq!(
+            filenames(&list),
+            [
+                "C:\\My Music\\a.mp3",
+                "/music/The \"Best\" Of.mp3",
+                "C:\\My Music\\",
+                "\"half quoted.mp3",
+                "plain.mp3"
+            ]
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        assert_eq!(filenames(&list)[0], "\"C:\\My Music\\a.mp3\"");
+    }
+
+    #[test]
+    fn pretty_output_reparses_to_the_same_playlist() {
+        let text = concat!(
+            "#EXTM3U\n#PLAYLIST:Mix\n",
+            "#EXTINF:3,A\na.mp3\n",
+            "#EXTGRP:Loose\nb.mp3\n",
+            "#EXTINF:-1 tvg-id=\"r\",Radio\n#EXTVLCOPT:network-caching=1000\nhttp://radio/s\n",
+            "#EXTINF:212,Long\n#EXTBYT:1024\nc.mp3\n",
+            "d.mp3\n",
+        );
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let pretty = save(&list, &SaveOptions { pretty: true, ..Default::default() });
+        // Padded to the width of `#EXTINF:-1 tvg-id="r"`
+        assert!(pretty.contains("#EXTINF:3            ,A\na.mp3\n\n#EXTGRP:Loose\nb.mp3\n"));
+        assert!(pretty.contains("#EXTINF:212          ,Long\n"));
+        let reread = M3u::parse("x.m3u", &pretty, &ParseOptions::default()).unwrap();
+        assert_eq!(*reread.entries.borrow(), *list.entries.borrow());
+        assert_eq!(reread.get_metadata(), list.get_metadata());
+        // Entries without an `#EXTINF` line don't widen the others
+        let list =
+            M3u::parse("x.m3u", "#EXTINF:3,A\na.mp3\n#EXTGRP:G\nb.mp3\n", &Default::default());
+        let pretty = save(&list.unwrap(), &SaveOptions { pretty: true, ..Default::default() });
+        assert_eq!(pretty, "#EXTM3U\n#EXTINF:3,A\na.mp3\n\n#EXTGRP:G\nb.mp3\n");
+    }
+
+    #[test]
+    fn play_times_round_trip() {
+        let text = "#EXTM3U\n#EXTINF:3,A\n#EXT-ABS-PLAYED:1700000000\na.mp3\nb.mp3\n";
+        let list = M3u::parse("x.m3u", text, &ParseOptions::default()).unwrap();
+        let played = list.entries.borrow()[0].metadata().unwrap().played_at;
+        assert_eq!(played, Some(UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)));
+        assert_eq!(save(&list, &SaveOptions::default()), text);
+        let err =
+            M3u::parse("x.m3u", "#EXT-ABS-PLAYED:yesterday\na.mp3\n", &Default::default());
+        assert!(matches!(err.err().unwrap(), PlaylistError::Parse { line: Some(1), .. }));
+    }
+}
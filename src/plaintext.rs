@@ -19,23 +19,48 @@ use std::cell::RefCell;
 
 use super::*;
 
+#[derive(Default)]
 pub struct PlainEntry<'a> {
     pub num: u32,
     pub fname: Cow<'a, str>,
     pub metadata: RefCell<Option<PlainMetadata<'a>>>,
 }
 
+impl PlainEntry<'_> {
+    /// Deep-copy the entry into one that doesn't borrow from anything.
+    ///
+    /// This detaches the entry from the buffer it was parsed from, so it can be stored
+    /// long-term or sent elsewhere. The metadata is not carried over, since
+    /// [`PlainMetadata`] borrows the entry it belongs to. It doesn't hold any information
+    /// of its own, so nothing is lost.
+    pub fn clone_owned(&self) -> PlainEntry<'static> {
+        PlainEntry {
+            num: self.num,
+            fname: Cow::Owned(self.fname.to_string()),
+            metadata: RefCell::new(None),
+        }
+    }
+}
+
 impl<'a> Entry<PlainMetadata<'a>> for PlainEntry<'a> {
     fn entry_num(&self) -> u32 {
-        todo!()
+        self.num
+    }
+
+    fn filename(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.fname)
+    }
+
+    fn set_entry_num(&mut self, num: u32) {
+        self.num = num;
     }
 
-    fn filename(&self) -> Cow<str> {
-        todo!()
+    fn set_filename(&mut self, fname: String) {
+        self.fname = Cow::Owned(fname);
     }
 
     fn metadata(&self) -> Option<PlainMetadata<'a>> {
-        self.metadata.try_borrow().ok().map(|m| m.clone()).flatten()
+        self.metadata.try_borrow().ok().and_then(|m| m.clone())
     }
 
     /// Replaces the currently stored metadata
@@ -57,10 +82,6 @@ impl PartialEq for PlainMetadata<'_> {
     fn eq(&self, other: &Self) -> bool {
         self.info() == other.info() && std::ptr::eq(self.parent, other.parent)
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
 }
 
 impl EntryMetadata for PlainMetadata<'_> {
@@ -76,3 +97,23 @@ impl EntryMetadata for PlainMetadata<'_> {
         ""
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owned_clones_outlive_their_buffer() {
+        let owned = {
+            let buffer = String::from("/music/a.mp3\n");
+            let entry = PlainEntry {
+                num: 3,
+                fname: Cow::Borrowed(buffer.trim_end()),
+                metadata: RefCell::new(None),
+            };
+            entry.clone_owned()
+        };
+        assert_eq!((owned.num, &*owned.fname), (3, "/music/a.mp3"));
+        assert!(matches!(owned.fname, Cow::Owned(_)));
+    }
+}
@@ -0,0 +1,32 @@
+//! Everything you need for day-to-day use, in one import.
+//!
+//! The traits are fully generic over their info, metadata and entry types, which gets
+//! verbose quickly. This module re-exports the traits and concrete format types, along
+//! with aliases like [`M3u`] for the fully specified playlists, so that a single
+//! `use libabsinthium::prelude::*;` covers the common cases.
+//!
+//! ```
+//! use libabsinthium::prelude::*;
+//!
+//! let text = "#EXTM3U\n#PLAYLIST:Roadtrip\n#EXTINF:212,Bohemian Rhapsody\nqueen.mp3\n";
+//! let list = M3u::parse("roadtrip.m3u", text, &ParseOptions::default())?;
+//! list.add_entry(M3uEntryBuilder::new("radio.mp3").num(2).title("Radio Ga Ga").build());
+//!
+//! let path = std::env::temp_dir().join("absinthium-prelude-roadtrip.m3u");
+//! list.save_to(&path, &SaveOptions::default())?;
+//! let saved = libabsinthium::open(path.to_str().unwrap())?;
+//! assert_eq!(saved.title().as_deref(), Some("Roadtrip"));
+//! assert_eq!(saved.count(), 2);
+//! assert_eq!(saved.entry_display_name(1).as_deref(), Some("Radio Ga Ga"));
+//! # std::fs::remove_file(path)?;
+//! # Ok::<(), PlaylistError>(())
+//! ```
+
+pub use crate::{
+    DedupStrategy, Entry, EntryMetadata, NamePreference, NormalizeOptions, Normalizer,
+    NumberingBase, ParseOptions, Playlist, PlaylistError, PlaylistFormat, PlaylistInfo,
+    PlaylistOps, SaveOptions, TitleFallback,
+    m3u::{DurationKind, M3u, M3uEntry, M3uEntryBuilder, M3uInfo, M3uMetadata},
+    plaintext::{PlainEntry, PlainMetadata},
+    registry::FormatRegistry,
+};
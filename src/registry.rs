@@ -0,0 +1,204 @@
+//! Runtime dispatch to playlist formats, built-in or not.
+//!
+//! A [`FormatRegistry`] holds a list of formats, each made up of a detector that looks at
+//! a file and a loader that turns it into a playlist. This lets applications plug in
+//! handlers for formats this crate doesn't know about, without having to touch the crate
+//! itself. All loaders in a registry produce the same type, so a registry of custom
+//! formats has to pick one they can all convert into.
+
+use super::*;
+use crate::m3u::{M3u, strip_directive};
+
+/// Decides whether a format handles a file, given its filename and text.
+type Detector = Box<dyn Fn(&str, &str) -> bool>;
+/// Loads a file the matching [`Detector`] accepted, given its filename and text.
+type Loader<T> = Box<dyn Fn(&str, &str) -> Result<T, PlaylistError>>;
+
+/// A registered format.
+struct Format<T> {
+    name: String,
+    detect: Detector,
+    load: Loader<T>,
+}
+
+/// A list of playlist formats to pick from when loading a file.
+///
+/// Formats are tried in the reverse order they were registered in, so anything added
+/// later takes precedence. That way a custom handler can take over files a built-in one
+/// would otherwise claim.
+pub struct FormatRegistry<T> {
+    formats: Vec<Format<T>>,
+}
+
+impl<T> FormatRegistry<T> {
+    /// Create a registry without any formats in it.
+    pub fn new() -> Self {
+        Self { formats: Vec::new() }
+    }
+
+    /// Add a format, going by `name`, with a `detect` closure deciding which files it
+    /// handles and a `load` closure reading them. Both get the filename and the text.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        detect: impl Fn(&str, &str) -> bool + 'static,
+        load: impl Fn(&str, &str) -> Result<T, PlaylistError> + 'static,
+    ) {
+        self.formats.push(Format {
+            name: name.into(),
+            detect: Box::new(detect),
+            load: Box::new(load),
+        });
+    }
+
+    /// Get the name of the format that would be used to load a file.
+    pub fn detect(&self, fname: &str, text: &str) -> Option<&str> {
+        self.find(fname, text).map(|format| format.name.as_str())
+    }
+
+    /// Load a file with the first format that claims it.
+    ///
+    /// ## Errors
+    /// Returns [`PlaylistError::Parse`] if no format recognizes the file, along with
+    /// whatever the loader of the format that does may return.
+    pub fn detect_and_load(&self, fname: &str, text: &str) -> Result<T, PlaylistError> {
+        match self.find(fname, text) {
+            Some(format) => (format.load)(fname, text),
+            None => Err(PlaylistError::Parse {
+                line: None,
+                message: format!("no registered format recognizes `{fname}`"),
+            }),
+        }
+    }
+
+    /// Find the format to use for a file.
+    fn find(&self, fname: &str, text: &str) -> Option<&Format<T>> {
+        self.formats.iter().rev().find(|format| (format.detect)(fname, text))
+    }
+}
+
+impl FormatRegistry<M3u<'static>> {
+    /// Create a registry with the built-in formats already registered.
+    ///
+    /// That's EXT-M3U, which also covers plain lists of paths, recognized by its `.m3u`
+    /// and `.m3u8` extensions or an `#EXTM3U` header. The header is found in any case, and
+    /// may follow a `#!` line. A `.txt` file only counts if its text reads like a list of
+    /// paths, so notes lying around next to playlists aren't taken for one.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "m3u",
+            |fname, text| {
+                let mut lines = text.trim_start_matches('\u{feff}').lines().peekable();
+                lines.next_if(|line| line.starts_with("#!"));
+                let by_extension = match extension(fname).as_deref() {
+                    Some("m3u" | "m3u8") => true,
+                    Some("txt") => lists_paths(text),
+                    _ => false,
+                };
+                by_extension
+                    || lines
+                        .map(str::trim)
+                        .find(|line| !line.is_empty())
+                        .is_some_and(|line| strip_directive(line, "#EXTM3U").is_some())
+            },
+            |fname, text| Ok(M3u::parse(fname, text, &ParseOptions::default())?.into_owned()),
+        );
+        registry
+    }
+}
+
+/// Check whether the text of a file reads like a list of paths or URIs, going by its first
+/// few lines that aren't blank or comments. Those all need a path separator, a scheme, or
+/// an extension that could belong to a media file.
+fn lists_paths(text: &str) -> bool {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .take(16)
+        .peekable();
+    lines.peek().is_some()
+        && lines.all(|line| {
+            line.contains(['/', '\\'])
+                || has_scheme(line)
+                || extension(line).is_some_and(|ext| {
+                    ext.chars().any(|c| c.is_ascii_alphabetic())
+                        && !ext.contains(char::is_whitespace)
+                })
+        })
+}
+
+impl<T> Default for FormatRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for FormatRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.formats.iter().map(|format| &format.name);
+        f.debug_struct("FormatRegistry").field("formats", &names.collect::<Vec<_>>()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_formats_load_through_the_registry() {
+        let mut registry = FormatRegistry::builtin();
+        // One path per line after a `TRACKS` header
+        registry.register(
+            "tracks",
+            |_, text| text.starts_with("TRACKS\n"),
+            |fname, text| {
+                let list = M3u::parse(fname, &text["TRACKS\n".len()..], &Default::default());
+                Ok(list?.into_owned())
+            },
+        );
+        assert_eq!(registry.detect("mix.m3u", "TRACKS\na.mp3\n"), Some("tracks"));
+        let list = registry.detect_and_load("mix.m3u", "TRACKS\na.mp3\nb.mp3\n").unwrap();
+        let fnames: Vec<_> =
+            list.entries.borrow().iter().map(|e| e.filename().into_owned()).collect();
+        assert_eq!(fnames, ["a.mp3", "b.mp3"]);
+        assert_eq!(registry.detect("mix.m3u", "a.mp3\n"), Some("m3u"));
+        let err = registry.detect_and_load("mix.xspf", "<playlist/>").err().unwrap();
+        assert!(matches!(err, PlaylistError::Parse { line: None, .. }));
+    }
+
+    #[test]
+    fn builtin_detects_the_header_in_any_case() {
+        let registry = FormatRegistry::builtin();
+        for text in [
+            "#EXTM3U\na.mp3\n",
+            "\u{feff}#ExtM3U\na.mp3\n",
+            "#!/usr/bin/env mpv\n#extm3u\na.mp3\n",
+            "\n  #EXTM3U x-tvg-url=\"a\"\n",
+        ] {
+            assert_eq!(registry.detect("list", text), Some("m3u"), "{text:?}");
+        }
+        for text in ["a.mp3\n#EXTM3U\n", "#!/bin/sh\n#!/bin/sh\n#EXTM3U\n", "#EXTM3"] {
+            assert_eq!(registry.detect("list", text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn builtin_only_takes_text_files_listing_paths() {
+        let registry = FormatRegistry::builtin();
+        for text in [
+            "a.mp3\nb.flac\n",
+            "# My mix\n/music/a\nC:\\Music\\b.mp3\n\nhttp://radio/stream\n",
+            "#EXTM3U\nNot a path\n",
+        ] {
+            assert_eq!(registry.detect("list.txt", text), Some("m3u"), "{text:?}");
+        }
+        for text in
+            ["Remember to buy milk\nCall mom\n", "Version 1.2\n", "Done. and more\n", ""]
+        {
+            assert_eq!(registry.detect("notes.txt", text), None, "{text:?}");
+        }
+        assert_eq!(registry.detect("notes.m3u", "Remember to buy milk\n"), Some("m3u"));
+    }
+}